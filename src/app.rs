@@ -11,12 +11,41 @@ pub struct App {
     pub scheduler: Pin<Box<core::Scheduler>>,
     output_image: TextureId,
     input_sender: InputSender,
+    inspector: Inspector,
+}
+
+create_states!(AppState { Playing, Paused });
+
+create_system!(toggle_pause_system, get_toggle_pause_system;
+    reads Input, State<AppState>; writes NextState<AppState>);
+pub async fn toggle_pause_system(game_state: &mut GameState, _t: f64, _dt: f64) {
+    let input = match game_state.get_resource::<Input>() {
+        Ok(input) => input,
+        Err(e) => return e.report(),
+    };
+    if !input.is_key_just_pressed(egui::Key::Escape) {
+        return;
+    }
+
+    let next = match game_state.state::<AppState>() {
+        Some(AppState::Playing) => AppState::Paused,
+        Some(AppState::Paused) | None => AppState::Playing,
+    };
+    if let Err(e) = game_state.set_next_state(next) {
+        e.report();
+    }
 }
 
 create_system!(rotate_system, get_rotate_system;
-    uses Player, Transform);
+    reads Input, Map, Player; writes Transform);
 pub async fn rotate_system(game_state: &mut GameState, _t: f64, dt: f64) {
-    let input = game_state.get_resource::<Input>().unwrap();
+    let (input, map) = match (
+        game_state.get_resource::<Input>(),
+        game_state.get_resource::<Map>(),
+    ) {
+        (Ok(input), Ok(map)) => (input, map),
+        (Err(e), _) | (_, Err(e)) => return e.report(),
+    };
 
     let delta_rotation = dt
         * (if input.is_key_pressed(egui::Key::ArrowLeft) {
@@ -40,10 +69,8 @@ pub async fn rotate_system(game_state: &mut GameState, _t: f64, dt: f64) {
         if move_forward { 3. } else { 0. } + if move_backward { -3. } else { 0. };
     let mut move_right: f32 = if move_right { 3. } else { 0. } + if move_left { -3. } else { 0. };
 
-    let player = &mut game_state.get_entities_with_mut::<Player>(Player::get_component_type())[0];
-    let player = player
-        .get_component_mut::<Transform>(Transform::get_component_type())
-        .unwrap();
+    let mut players = game_state.query_mut::<&mut Transform, With<Player>>();
+    let mut player = players.single_mut().unwrap();
 
     player.rotation += delta_rotation as f32;
 
@@ -57,15 +84,13 @@ pub async fn rotate_system(game_state: &mut GameState, _t: f64, dt: f64) {
     }
 
     if move_forward.abs() == move_right.abs() {
-        move_forward = move_forward / 1.41421356;
-        move_right = move_right / 1.41421356;
+        move_forward /= std::f32::consts::SQRT_2;
+        move_right /= std::f32::consts::SQRT_2;
     }
 
     player.position[0] += sin * move_forward * dt as f32 - cos * move_right * dt as f32;
     player.position[1] += cos * move_forward * dt as f32 + sin * move_right * dt as f32;
 
-    let map = game_state.get_resource::<Map>().unwrap();
-
     for direction in map.intersects_rect(player.position[0], player.position[1], 0.5, 0.5) {
         match direction {
             Direction::Up | Direction::Down => player.position[1] = prev_pos[1],
@@ -89,9 +114,19 @@ impl App {
 
         let (input, input_sender) = Input::new();
 
-        game_state.add_resource(gpu);
-        game_state.add_resource(networking);
-        game_state.add_resource(input);
+        game_state.insert_resource(gpu);
+        game_state.insert_resource(networking);
+        game_state.insert_resource(input);
+        game_state.add_state(AppState::Playing);
+
+        game_state.register_saveable::<Transform>();
+        game_state.register_saveable::<GlobalTransform>();
+        game_state.register_saveable::<Player>();
+
+        // nothing works without a player, so the game doesn't start without one either
+        if let Err(e) = game_state.spawn_prefab("player", PrefabOverrides::default()) {
+            panic!("Can't start without a player: {e}");
+        }
 
         scheduler.add_system_without_execution_order_generation(
             crate::render::get_init_system(),
@@ -99,32 +134,41 @@ impl App {
         );
 
         scheduler.add_system_without_execution_order_generation(
-            get_handle_input_system(),
+            get_handle_input_system().in_set(SystemSet::Input),
             SystemType::Update,
         );
         scheduler.add_system_without_execution_order_generation(
-            crate::render::get_render_system(),
+            crate::render::get_render_system().in_set(SystemSet::Render),
+            SystemType::Update,
+        );
+        scheduler.add_system_without_execution_order_generation(
+            get_rotate_system()
+                .in_set(SystemSet::Simulation)
+                .run_if(in_state(AppState::Playing)),
+            SystemType::FixedUpdate,
+        );
+        scheduler.add_system_without_execution_order_generation(
+            get_propagate_transforms_system().in_set(SystemSet::Simulation),
+            SystemType::Update,
+        );
+        scheduler.add_system_without_execution_order_generation(
+            get_toggle_pause_system().in_set(SystemSet::Simulation),
             SystemType::Update,
         );
-        scheduler
-            .add_system_without_execution_order_generation(get_rotate_system(), SystemType::Update);
-
-        scheduler.generate_execution_order();
-
-        scheduler.init(&mut *game_state).await;
 
-        /*let fixed_update_scheduler = unsafe { &*(&*scheduler as *const Scheduler) };
-        let fixed_update_future = fixed_update_scheduler.loop_fixed_update(&mut *game_state as *mut _);
-        let fixed_update_future = unsafe { SendBox::new(fixed_update_future) };
+        if let Err(e) = scheduler.generate_execution_order() {
+            panic!("{e}");
+        }
 
-        spawn(fixed_update_future);*/
-        // TODO: Improve the scheduler so that fixed update can be run at the same time as update, rather than the current implementation with pre-defined task execution groups
+        // fixed updates are run by `update` as time accumulates
+        scheduler.init(&mut game_state).await;
 
         App {
             game_state,
             scheduler,
             output_image,
             input_sender,
+            inspector: Inspector::default(),
         }
     }
 }
@@ -156,6 +200,8 @@ fn largest_16_9_rect(container: Rect) -> Rect {
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
+        self.inspector.show(ctx, &mut self.game_state);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.input(|reader| {
                 let events = reader.events.clone();
@@ -169,8 +215,24 @@ impl eframe::App for App {
             ui.put(rect, image);
         });
 
-        RT.block_on(self.scheduler.update(&mut *self.game_state));
+        RT.block_on(self.scheduler.update(&mut self.game_state));
+
+        // goes through `on_exit` like closing the window does
+        if self.game_state.should_close {
+            ctx.send_viewport_cmd(ViewportCommand::Close);
+        }
 
         ctx.request_repaint();
     }
+
+    fn on_exit(&mut self, _gl: Option<&glow::Context>) {
+        let closed = RT.block_on(
+            self.scheduler
+                .close_with_timeout(&mut self.game_state, CLOSE_TIMEOUT),
+        );
+        if !closed {
+            eprintln!("Close systems didn't finish within {CLOSE_TIMEOUT:?}, exiting anyway");
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,142 @@
+// Compares spawning and joining two component types through `GameState` against the layout it
+// used before the sparse sets. Run with `cargo bench`, `cargo test` runs every benchmark once
+extern crate test;
+
+use crate::*;
+use std::any::Any;
+use std::cell::SyncUnsafeCell;
+use std::pin::Pin;
+use std::sync::Arc;
+use test::{black_box, Bencher};
+
+const ENTITIES: u32 = 10_000;
+
+const POSITION: usize = 0;
+const VELOCITY: usize = 1;
+
+#[derive(Debug)]
+struct Position([f32; 2]);
+impl_component!(Position);
+
+#[derive(Debug)]
+struct Velocity([f32; 2]);
+impl_component!(Velocity);
+
+// every component boxed behind its own `Arc`, listed once per type and once per owning entity
+struct ComponentStruct {
+    component: Box<dyn Any + Send + Sync>,
+    owner: u32,
+    component_type: usize,
+}
+
+type ComponentRef = Arc<SyncUnsafeCell<ComponentStruct>>;
+
+struct OldEntity {
+    components: Vec<ComponentRef>,
+}
+
+struct OldLayout {
+    entities: Vec<OldEntity>,
+    components: Vec<Vec<ComponentRef>>,
+}
+
+impl OldLayout {
+    fn new(count: u32) -> Self {
+        let mut layout = OldLayout {
+            entities: Vec::new(),
+            components: vec![Vec::new(), Vec::new()],
+        };
+
+        for owner in 0..count {
+            let mut entity = OldEntity {
+                components: Vec::new(),
+            };
+            let components: [(Box<dyn Any + Send + Sync>, usize); 2] = [
+                (Box::new(Position([owner as f32, 0.0])), POSITION),
+                (Box::new(Velocity([1.0, 1.0])), VELOCITY),
+            ];
+            for (component, component_type) in components {
+                let component = Arc::new(SyncUnsafeCell::new(ComponentStruct {
+                    component,
+                    owner,
+                    component_type,
+                }));
+                entity.components.push(component.clone());
+                layout.components[component_type].push(component);
+            }
+            layout.entities.push(entity);
+        }
+
+        layout
+    }
+
+    // what `get_entities_with` + `get_component_mut` did: owner lookup, then a linear scan
+    fn step(&mut self) {
+        for velocity in &self.components[VELOCITY] {
+            let velocity = unsafe { &*velocity.get() };
+            let entity = &self.entities[velocity.owner as usize];
+            let position = entity
+                .components
+                .iter()
+                .map(|component| unsafe { &mut *component.get() })
+                .find(|component| component.component_type == POSITION)
+                .unwrap();
+
+            let velocity = velocity.component.downcast_ref::<Velocity>().unwrap();
+            let position = position.component.downcast_mut::<Position>().unwrap();
+            position.0[0] += velocity.0[0];
+            position.0[1] += velocity.0[1];
+        }
+    }
+}
+
+// the `GameState` points back at its scheduler, so it is declared first to be dropped first
+struct World {
+    game_state: Pin<Box<GameState>>,
+    _scheduler: Pin<Box<Scheduler>>,
+}
+
+fn spawn(count: u32) -> World {
+    let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+    let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+
+    for index in 0..count {
+        let entity = game_state.create_entity("mover".to_string());
+        entity.add_component(&mut game_state, Position([index as f32, 0.0]));
+        entity.add_component(&mut game_state, Velocity([1.0, 1.0]));
+    }
+
+    World {
+        game_state,
+        _scheduler: scheduler,
+    }
+}
+
+fn step(game_state: &mut GameState) {
+    for (mut position, velocity) in game_state.query_mut::<(&mut Position, &Velocity), ()>() {
+        position.0[0] += velocity.0[0];
+        position.0[1] += velocity.0[1];
+    }
+}
+
+#[bench]
+fn spawn_arc_list(bencher: &mut Bencher) {
+    bencher.iter(|| OldLayout::new(ENTITIES));
+}
+
+#[bench]
+fn spawn_game_state(bencher: &mut Bencher) {
+    bencher.iter(|| spawn(ENTITIES));
+}
+
+#[bench]
+fn query_arc_list(bencher: &mut Bencher) {
+    let mut layout = OldLayout::new(ENTITIES);
+    bencher.iter(|| black_box(&mut layout).step());
+}
+
+#[bench]
+fn query_game_state(bencher: &mut Bencher) {
+    let mut world = spawn(ENTITIES);
+    bencher.iter(|| step(black_box(&mut world.game_state)));
+}
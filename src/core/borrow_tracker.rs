@@ -0,0 +1,227 @@
+use crate::core::*;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct Borrows {
+    shared: Vec<SystemId>,
+    exclusive: Option<SystemId>,
+}
+
+struct Declared {
+    name: &'static str,
+    access: SystemAccess,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    // what every system in the running execution group declared, by id as names can repeat
+    declared: HashMap<SystemId, Declared>,
+    borrows: HashMap<ComponentType, Borrows>,
+}
+
+impl TrackerState {
+    fn name(&self, system: SystemId) -> &'static str {
+        self.declared[&system].name
+    }
+}
+
+/// Catches systems that touch component types or resources they did not declare.
+///
+/// The `GameState` accessors hand out `&'static` references, so nothing stops two systems in
+/// the same execution group from aliasing the same data. In debug builds every access made
+/// from inside a system is recorded here, and any access outside of what the system declared, or
+/// conflicting with another system's borrow, panics naming both systems. In release builds
+/// this does nothing.
+#[derive(Default)]
+pub struct BorrowTracker {
+    state: Mutex<TrackerState>,
+}
+
+fn type_name(component_type: ComponentType) -> &'static str {
+    get_component_type_name(component_type).unwrap_or("<unknown>")
+}
+
+impl BorrowTracker {
+    // called by the `Scheduler` before the systems of a group start running
+    pub fn begin_group<'a>(&self, systems: impl Iterator<Item = &'a System>) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.declared = systems
+            .map(|system| {
+                let declared = Declared {
+                    name: system.name,
+                    access: system.access.clone(),
+                };
+                (system.id, declared)
+            })
+            .collect();
+        state.borrows.clear();
+    }
+
+    // references can't be taken back, so everything stays borrowed until the group is done
+    pub fn end_group(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.declared.clear();
+        state.borrows.clear();
+    }
+
+    pub fn borrow_component<T: Component>(&self, exclusive: bool) {
+        if cfg!(debug_assertions) {
+            self.borrow(T::get_component_type(), exclusive);
+        }
+    }
+
+    pub fn borrow_resource<T: Resource>(&self, exclusive: bool) {
+        if cfg!(debug_assertions) {
+            self.borrow(T::get_component_type(), exclusive);
+        }
+    }
+
+    // for state shared by every entity, like the name index, that only `GameState` access covers
+    pub fn borrow_game_state(&self, exclusive: bool) {
+        if cfg!(debug_assertions) {
+            self.borrow(GameState::get_component_type(), exclusive);
+        }
+    }
+
+    fn borrow(&self, component_type: ComponentType, exclusive: bool) {
+        // accesses from outside of systems, like `App::new` or applying commands, can't race
+        let Some(context) = SystemContext::try_current() else {
+            return;
+        };
+
+        // the lock is released before panicking so it doesn't get poisoned
+        let error = self.record(context.id, component_type, exclusive);
+        if let Some(error) = error {
+            panic!("{error}");
+        }
+    }
+
+    fn record(
+        &self,
+        system: SystemId,
+        component_type: ComponentType,
+        exclusive: bool,
+    ) -> Option<String> {
+        let kind = if exclusive { "mutably" } else { "immutably" };
+        let name = type_name(component_type);
+
+        let mut state = self.state.lock().unwrap();
+        let declared = &state.declared.get(&system)?.access;
+        let system_name = state.name(system);
+        // writing `GameState` makes a system run on its own
+        if declared.writes.contains(&GameState::get_component_type()) {
+            return None;
+        }
+
+        let (allowed, clause) = match exclusive {
+            true => (declared.can_write(component_type), "`writes`"),
+            false => (declared.can_read(component_type), "`reads` or `writes`"),
+        };
+        if !allowed {
+            let mut attempted = SystemAccess::default();
+            match exclusive {
+                true => attempted.write(component_type),
+                false => attempted.read(component_type),
+            }
+            let mut others: Vec<&str> = state
+                .declared
+                .iter()
+                .filter(|(other, declared)| {
+                    **other != system && attempted.conflicts_with(&declared.access)
+                })
+                .map(|(_, declared)| declared.name)
+                .collect();
+            others.sort();
+
+            if others.is_empty() {
+                return Some(format!(
+                    "system `{system_name}` borrows `{name}` {kind} without declaring it in {clause}"
+                ));
+            }
+            return Some(format!(
+                "system `{system_name}` borrows `{name}` {kind} without declaring it in {clause}, \
+                 conflicting with `{}` which runs at the same time",
+                others.join("`, `")
+            ));
+        }
+
+        let borrows = state.borrows.entry(component_type).or_default();
+        let conflict = match borrows.exclusive {
+            Some(other) if other != system => Some(other),
+            _ if exclusive => borrows
+                .shared
+                .iter()
+                .copied()
+                .find(|other| *other != system),
+            _ => None,
+        };
+        if let Some(other) = conflict {
+            let other_name = state.name(other);
+            return Some(format!(
+                "system `{system_name}` borrows `{name}` {kind} while `{other_name}` is already \
+                 borrowing it"
+            ));
+        }
+
+        if exclusive {
+            borrows.exclusive = Some(system);
+        } else if !borrows.shared.contains(&system) {
+            borrows.shared.push(system);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    // same name, different access
+    mod reader {
+        use super::*;
+
+        create_system!(touch, get_touch; reads Transform);
+        pub async fn touch(game_state: &mut GameState, _t: f64, _dt: f64) {
+            for transform in game_state.query::<&Transform, ()>() {
+                assert_eq!(transform.scale, [1.0, 1.0]);
+            }
+        }
+    }
+
+    mod idle {
+        use super::*;
+
+        create_system!(touch, get_touch);
+        pub async fn touch(_game_state: &mut GameState, _t: f64, _dt: f64) {}
+    }
+
+    #[tokio::test]
+    async fn systems_with_the_same_name_keep_their_own_access() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        let transform = Transform {
+            position: [0.0, 0.0],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+        };
+        let entity = game_state.create_entity("spinner".to_string());
+        entity.add_component(&mut game_state, transform);
+
+        scheduler
+            .add_system(reader::get_touch(), SystemType::Update)
+            .unwrap();
+        scheduler
+            .add_system(idle::get_touch(), SystemType::Update)
+            .unwrap();
+        scheduler.step(&mut game_state, 0.1).await;
+    }
+}
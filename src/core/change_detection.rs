@@ -0,0 +1,89 @@
+use crate::core::*;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+/// Mutable access to a component that only marks it as changed once it is actually written to.
+///
+/// This is what a query for `&mut T` yields, so reading through it keeps `Changed<T>` quiet.
+pub struct Mut<'a, T> {
+    value: &'a mut T,
+    ticks: &'a mut ComponentTicks,
+    this_run: u32,
+}
+
+impl<'a, T> Mut<'a, T> {
+    pub fn new(value: &'a mut T, ticks: &'a mut ComponentTicks, this_run: u32) -> Self {
+        Mut {
+            value,
+            ticks,
+            this_run,
+        }
+    }
+
+    pub fn ticks(&self) -> ComponentTicks {
+        *self.ticks
+    }
+
+    pub fn into_inner(self) -> &'a mut T {
+        self.ticks.changed = self.this_run;
+        self.value
+    }
+
+    // for writes that other systems should not react to, like correcting a position in place
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<T> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.ticks.changed = self.this_run;
+        self.value
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Mut<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// Matches entities whose `T` was added since the system last ran
+pub struct Added<T: Component>(PhantomData<T>);
+
+/// Matches entities whose `T` was added or mutably accessed since the system last ran
+pub struct Changed<T: Component>(PhantomData<T>);
+
+impl<T: Component> QueryFilter for Added<T> {
+    fn access(access: &mut QueryAccess) {
+        access.required.push(T::get_component_type());
+    }
+
+    fn matches(game_state: &GameState, context: &SystemContext, entity: EntityId) -> bool {
+        game_state
+            .get_storage::<T>()
+            .and_then(|storage| storage.get_ticks(entity))
+            .is_some_and(|ticks| ticks.is_added(context.last_run, context.this_run))
+    }
+}
+
+impl<T: Component> QueryFilter for Changed<T> {
+    fn access(access: &mut QueryAccess) {
+        access.required.push(T::get_component_type());
+    }
+
+    fn matches(game_state: &GameState, context: &SystemContext, entity: EntityId) -> bool {
+        game_state
+            .get_storage::<T>()
+            .and_then(|storage| storage.get_ticks(entity))
+            .is_some_and(|ticks| ticks.is_changed(context.last_run, context.this_run))
+    }
+}
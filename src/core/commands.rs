@@ -0,0 +1,161 @@
+use crate::core::*;
+use std::sync::Mutex;
+
+pub type Command = Box<dyn FnOnce(&mut GameState) + Send>;
+
+// Systems in the same execution group run at the same time, so structural changes (spawning,
+// despawning, adding and removing components) are queued here and applied by the `Scheduler`
+// once the group has finished
+#[derive(Default)]
+pub struct CommandQueue {
+    commands: Mutex<Vec<Command>>,
+}
+
+impl CommandQueue {
+    pub fn push(&self, command: Command) {
+        self.commands.lock().unwrap().push(command);
+    }
+
+    pub fn take(&self) -> Vec<Command> {
+        std::mem::take(&mut *self.commands.lock().unwrap())
+    }
+}
+
+/// Queues changes to a `GameState` to be applied after the current execution group.
+///
+/// Only needs shared access, so any system can use it without declaring what it touches.
+/// Commands targeting an entity that has been despawned by the time they run do nothing.
+pub struct Commands<'a> {
+    queue: &'a CommandQueue,
+}
+
+impl<'a> Commands<'a> {
+    pub fn new(queue: &'a CommandQueue) -> Self {
+        Commands { queue }
+    }
+
+    pub fn add(&self, command: impl FnOnce(&mut GameState) + Send + 'static) {
+        self.queue.push(Box::new(command));
+    }
+
+    // `build` runs once the entity exists, which is also the earliest its id is known
+    pub fn spawn(
+        &self,
+        name: impl Into<String>,
+        build: impl FnOnce(&mut Entity, &mut GameState) + Send + 'static,
+    ) {
+        let name = name.into();
+        self.add(move |game_state| {
+            let entity = game_state.create_entity(name);
+            build(entity, game_state);
+        });
+    }
+
+    // there is no one to return an error to, so a prefab that fails to spawn is only reported
+    pub fn spawn_prefab(
+        &self,
+        path: impl Into<String>,
+        overrides: PrefabOverrides,
+        build: impl FnOnce(&mut Entity, &mut GameState) + Send + 'static,
+    ) {
+        let path = path.into();
+        self.add(
+            move |game_state| match game_state.spawn_prefab(&path, overrides) {
+                Ok(entity) => build(entity, game_state),
+                Err(e) => eprintln!("{e}"),
+            },
+        );
+    }
+
+    pub fn despawn(&self, entity: EntityId) {
+        self.add(move |game_state| {
+            game_state.despawn_entity(entity);
+        });
+    }
+
+    pub fn despawn_recursive(&self, entity: EntityId) {
+        self.add(move |game_state| {
+            game_state.despawn_recursive(entity);
+        });
+    }
+
+    pub fn insert<T: Component>(&self, entity: EntityId, component: T) {
+        self.add(move |game_state| {
+            if let Some(entity) = game_state.get_entity_mut(entity) {
+                entity.add_component(game_state, component);
+            }
+        });
+    }
+
+    pub fn remove<T: Component>(&self, entity: EntityId) {
+        self.add(move |game_state| {
+            game_state.remove_component::<T>(entity);
+        });
+    }
+
+    pub fn insert_resource<T: Resource>(&self, resource: T) {
+        self.add(move |game_state| {
+            game_state.insert_resource(resource);
+        });
+    }
+
+    pub fn remove_resource<T: Resource>(&self) {
+        self.add(move |game_state| {
+            game_state.remove_resource::<T>();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static SEEN: AtomicUsize = AtomicUsize::new(0);
+
+    create_system!(tag_players, get_tag_players; reads Transform);
+    async fn tag_players(game_state: &mut GameState, _t: f64, _dt: f64) {
+        for (entity, _) in game_state.query::<(EntityId, &Transform), Without<Player>>() {
+            game_state.commands().insert(entity, Player);
+        }
+    }
+
+    create_system!(count_new_players, get_count_new_players; reads Player);
+    async fn count_new_players(game_state: &mut GameState, _t: f64, _dt: f64) {
+        let added = game_state.query::<EntityId, Added<Player>>().iter().count();
+        SEEN.fetch_add(added, Ordering::Relaxed);
+    }
+
+    #[tokio::test]
+    async fn inserts_are_added_for_systems_in_the_same_group() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        let transform = Transform {
+            position: [0.0, 0.0],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+        };
+        let entity = game_state.create_entity("player".to_string());
+        entity.add_component(&mut game_state, transform);
+
+        scheduler
+            .add_system(get_tag_players(), SystemType::Update)
+            .unwrap();
+        scheduler
+            .add_system(get_count_new_players(), SystemType::Update)
+            .unwrap();
+        // both only read, so they share a group
+        assert_eq!(
+            scheduler
+                .get_schedule(SystemType::Update)
+                .execution_order
+                .len(),
+            1
+        );
+
+        for _ in 0..3 {
+            scheduler.step(&mut game_state, 0.1).await;
+        }
+        assert_eq!(SEEN.load(Ordering::Relaxed), 1);
+    }
+}
@@ -1,24 +1,86 @@
 use std::any::Any;
-use std::cell::SyncUnsafeCell;
+use std::collections::HashSet;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
 
 use crate::core::*;
 
-pub trait Resource: Any {
+pub trait Resource: Any + Send + Sync {
     fn as_any(&self) -> &dyn Any;
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    fn get_component_type() -> ComponentType
+    where
+        Self: Sized,
+    {
+        get_component_type_of::<Self>()
+    }
+}
+
+#[derive(Debug)]
+pub enum ResourceError {
+    Missing(&'static str),
+}
+
+lazy_static! {
+    static ref REPORTED_RESOURCE_ERRORS: Mutex<HashSet<(SystemId, &'static str)>> =
+        Mutex::new(HashSet::new());
+}
+
+impl ResourceError {
+    pub fn missing<T: Resource>() -> Self {
+        ResourceError::Missing(std::any::type_name::<T>())
+    }
+
+    // a system missing a resource fails the same way every frame, so it only reports it once
+    pub fn report(&self) {
+        if let Some(message) = self.report_message() {
+            eprintln!("{message}");
+        }
+    }
+
+    // `None` if the current system has already reported this error
+    fn report_message(&self) -> Option<String> {
+        let ResourceError::Missing(name) = self;
+        let Some(context) = SystemContext::try_current() else {
+            return Some(self.to_string());
+        };
+        REPORTED_RESOURCE_ERRORS
+            .lock()
+            .unwrap()
+            .insert((context.id, name))
+            .then(|| format!("{}: {self}", context.name))
+    }
+}
+
+impl std::fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ResourceError::Missing(name) => write!(
+                f,
+                "resource `{name}` does not exist, it has to be added with `insert_resource` first"
+            ),
+        }
+    }
 }
 
+impl std::error::Error for ResourceError {}
+
 macro_rules! impl_resource {
-    ($type:ty, $component_type:expr) => {
+    ($type:ty) => {
         impl crate::core::Resource for $type {
             fn as_any(&self) -> &dyn std::any::Any {
                 self
             }
-        }
-        impl $type {
-            pub const fn get_component_type() -> crate::core::ComponentType {
-                $component_type
+
+            fn get_component_type() -> crate::core::ComponentType {
+                crate::core::cached_component_type!()
             }
         }
     };
@@ -26,13 +88,14 @@ macro_rules! impl_resource {
 pub(crate) use impl_resource;
 
 pub struct Entity {
-    pub id: u32,
-    pub name: String,
-    pub components: Vec<Arc<SyncUnsafeCell<ComponentStruct>>>,
+    pub id: EntityId,
+    // only changed through `set_name` so the name index stays up to date
+    name: String,
+    pub components: Vec<ComponentType>,
 }
 
 impl Entity {
-    pub fn new(id: u32, name: String) -> Entity {
+    pub fn new(id: EntityId, name: String) -> Entity {
         Entity {
             id,
             name,
@@ -40,97 +103,344 @@ impl Entity {
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // the name index is shared by every entity, so inside a system this needs `writes GameState`
+    pub fn set_name(&mut self, game_state: &mut GameState, name: impl Into<String>) {
+        game_state.borrow_tracker.borrow_game_state(true);
+        let name = name.into();
+        game_state.name_index.remove(&self.name, self.id);
+        game_state.name_index.insert(&name, self.id);
+        self.name = name;
+    }
+
+    // replaces the previous value if the entity already has a `T`
     pub fn add_component<'a, T: Component + 'a>(
         &mut self,
         game_state: &mut GameState,
         component: T,
-        component_type: ComponentType,
     ) {
-        let rc = Arc::new(SyncUnsafeCell::new(ComponentStruct {
-            component: Box::new(component),
-            owner: self.id,
-            component_type,
-        }));
-
-        self.components.push(rc.clone());
-        game_state.components[component_type].push(rc);
-    }
-
-    pub fn get_component<'a, T: Component + 'a>(
-        &'a self,
-        component_type: ComponentType,
-    ) -> Option<&'a T> {
-        for component in &self.components {
-            if unsafe { &*component.get() }.component_type == component_type {
-                return unsafe {
-                    Some((&*(&*component.get()).component as &dyn Any).downcast_ref_unchecked())
-                };
-            }
+        let tick = game_state.change_tick();
+        let storage = game_state.get_storage_or_insert::<T>();
+        let component_type = T::get_component_type();
+        if storage.insert(self.id, component, tick).is_none() {
+            self.components.push(component_type);
+            game_state.trigger_lifecycle(Lifecycle::Add, component_type, self.id);
         }
-        None
+        game_state.trigger_lifecycle(Lifecycle::Insert, component_type, self.id);
     }
 
-    pub fn get_component_mut<'a, T: Component + 'a>(
-        &'a mut self,
-        component_type: ComponentType,
-    ) -> Option<&'a mut T> {
-        for component in &self.components {
-            if unsafe { &*component.get() }.component_type == component_type {
-                return unsafe {
-                    Some(
-                        (&mut *(&mut *component.get()).component as &mut dyn Any)
-                            .downcast_mut_unchecked(),
-                    )
-                };
-            }
+    // Inside a system, a component with `Remove` hooks or observers is only removed once the
+    // execution group is done, right after they ran, and `None` is returned
+    pub fn remove_component<T: Component>(&mut self, game_state: &mut GameState) -> Option<T> {
+        if !self.has_component(T::get_component_type()) {
+            return None;
         }
-        None
+        if game_state.defers_lifecycle(Lifecycle::Remove, T::get_component_type()) {
+            let entity = self.id;
+            game_state.commands().add(move |game_state| {
+                game_state.remove_component::<T>(entity);
+            });
+            return None;
+        }
+        // runs while the component can still be read
+        game_state.trigger_lifecycle(Lifecycle::Remove, T::get_component_type(), self.id);
+
+        let component = game_state.get_storage_mut::<T>()?.remove(self.id)?;
+        self.components
+            .retain(|component_type| *component_type != T::get_component_type());
+
+        Some(component)
     }
-}
 
-#[derive(Debug)]
-pub struct ComponentStruct {
-    pub component: Box<dyn Component>,
-    pub owner: u32,
-    pub component_type: ComponentType,
+    pub fn has_component(&self, component_type: ComponentType) -> bool {
+        self.components.contains(&component_type)
+    }
+
+    pub fn get_component<'a, T: Component>(&self, game_state: &'a GameState) -> Option<&'a T> {
+        game_state.get_component::<T>(self.id)
+    }
+
+    pub fn get_component_mut<'a, T: Component>(
+        &self,
+        game_state: &'a mut GameState,
+    ) -> Option<&'a mut T> {
+        game_state.get_component_mut::<T>(self.id)
+    }
 }
+
 pub type ComponentType = usize;
 
-pub trait Component: Any + std::fmt::Debug {
+pub trait Component: Any + std::fmt::Debug + Send + Sync {
     fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn get_component_type() -> ComponentType
+    where
+        Self: Sized,
+    {
+        get_component_type_of::<Self>()
+    }
 }
 
 macro_rules! impl_component {
-    ($type:ty, $comp_type:expr) => {
-        impl Component for $type {
-            fn as_any(&self) -> &dyn Any {
+    ($type:ty) => {
+        impl crate::core::Component for $type {
+            fn as_any(&self) -> &dyn std::any::Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
                 self
             }
-        }
 
-        impl $type {
-            pub const fn get_component_type() -> ComponentType {
-                $comp_type
+            fn get_component_type() -> crate::core::ComponentType {
+                crate::core::cached_component_type!()
             }
         }
     };
 }
 pub(crate) use impl_component;
 
+// Declares a zero-sized marker component, e.g. `create_tag!(Enemy)`. Its storage only keeps
+// track of which entities have it, so `With<Enemy>` and `Without<Enemy>` checks are O(1)
+macro_rules! create_tag {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(
+            Debug,
+            Clone,
+            Copy,
+            Default,
+            PartialEq,
+            Eq,
+            Hash,
+            serde::Serialize,
+            serde::Deserialize,
+        )]
+        pub struct $name;
+        crate::core::impl_component!($name);
+    };
+}
+pub(crate) use create_tag;
+
+/// Type-erased view of a `SparseSet<T>` so `GameState` can keep every storage in one list
+pub trait ComponentStorage: Any + Send + Sync {
+    fn contains(&self, entity: EntityId) -> bool;
+    fn get_ticks(&self, entity: EntityId) -> Option<ComponentTicks>;
+    fn remove(&mut self, entity: EntityId) -> bool;
+    fn entities(&self) -> &[EntityId];
+    fn get_dyn(&self, entity: EntityId) -> Option<&dyn Component>;
+    fn get_dyn_mut(&mut self, entity: EntityId) -> Option<&mut dyn Component>;
+    fn set_changed(&mut self, entity: EntityId, tick: u32);
+}
+
+impl<T: Component> ComponentStorage for SparseSet<T> {
+    fn contains(&self, entity: EntityId) -> bool {
+        SparseSet::contains(self, entity)
+    }
+
+    fn get_ticks(&self, entity: EntityId) -> Option<ComponentTicks> {
+        SparseSet::get_ticks(self, entity).copied()
+    }
+
+    fn remove(&mut self, entity: EntityId) -> bool {
+        SparseSet::remove(self, entity).is_some()
+    }
+
+    fn entities(&self) -> &[EntityId] {
+        SparseSet::entities(self)
+    }
+
+    fn get_dyn(&self, entity: EntityId) -> Option<&dyn Component> {
+        self.get(entity)
+            .map(|component| component as &dyn Component)
+    }
+
+    fn get_dyn_mut(&mut self, entity: EntityId) -> Option<&mut dyn Component> {
+        self.get_mut(entity)
+            .map(|component| component as &mut dyn Component)
+    }
+
+    fn set_changed(&mut self, entity: EntityId, tick: u32) {
+        if let Some((_, ticks)) = self.get_with_ticks_mut(entity) {
+            ticks.changed = tick;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SystemType {
     Init,
     Update,
     FixedUpdate,
     Close,
+    // run between frames, when a state machine enters or leaves the state
+    OnEnter(StateId),
+    OnExit(StateId),
+}
+
+pub type SystemFn = Box<
+    dyn Fn(*mut GameState, f64, f64) -> Pin<Box<dyn futures::Future<Output = ()> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// The component types and resources a system reads and writes.
+///
+/// Any number of systems reading the same type can run at the same time, while a system writing
+/// it runs apart from every other system that reads or writes it. Writing `GameState` gives a
+/// system exclusive access to everything and reading it lets it read everything.
+#[derive(Debug, Clone, Default)]
+pub struct SystemAccess {
+    pub reads: Vec<ComponentType>,
+    pub writes: Vec<ComponentType>,
+}
+
+impl SystemAccess {
+    pub fn read(&mut self, component_type: ComponentType) {
+        if !self.reads.contains(&component_type) && !self.writes.contains(&component_type) {
+            self.reads.push(component_type);
+        }
+    }
+
+    // writing implies reading
+    pub fn write(&mut self, component_type: ComponentType) {
+        self.reads.retain(|other| *other != component_type);
+        if !self.writes.contains(&component_type) {
+            self.writes.push(component_type);
+        }
+    }
+
+    pub fn extend(&mut self, other: &SystemAccess) {
+        for component_type in &other.reads {
+            self.read(*component_type);
+        }
+        for component_type in &other.writes {
+            self.write(*component_type);
+        }
+    }
+
+    pub fn can_read(&self, component_type: ComponentType) -> bool {
+        let everything = GameState::get_component_type();
+        self.reads.contains(&component_type)
+            || self.reads.contains(&everything)
+            || self.can_write(component_type)
+    }
+
+    pub fn can_write(&self, component_type: ComponentType) -> bool {
+        self.writes.contains(&component_type)
+            || self.writes.contains(&GameState::get_component_type())
+    }
+
+    fn writes_anything_read_by(&self, other: &SystemAccess) -> bool {
+        let everything = GameState::get_component_type();
+        if self.writes.is_empty() {
+            return false;
+        }
+        self.writes.contains(&everything)
+            || other.reads.contains(&everything)
+            || other.writes.contains(&everything)
+            || self
+                .writes
+                .iter()
+                .any(|component_type| other.can_read(*component_type))
+    }
+
+    pub fn conflicts_with(&self, other: &SystemAccess) -> bool {
+        self.writes_anything_read_by(other) || other.writes_anything_read_by(self)
+    }
+}
+
+// Identifies a system to pause, resume, remove or replace it through the `Scheduler`. Assigned
+// by `Scheduler::add_system` in the order systems are added, and stays the same when the system
+// is replaced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SystemId(pub(crate) u64);
+
+impl SystemId {
+    // what systems have until they are added to a scheduler
+    pub const UNASSIGNED: SystemId = SystemId(u64::MAX);
 }
 
 pub struct System {
-    pub args: Vec<ComponentType>,
-    pub system: Box<
-        dyn Fn(*mut GameState, f64, f64) -> Pin<Box<dyn futures::Future<Output = ()>>>
-            + Send
-            + Sync,
-    >,
+    pub id: SystemId,
+    pub name: &'static str,
+    pub access: SystemAccess,
+    pub labels: Vec<SystemLabel>,
+    pub before: Vec<SystemLabel>,
+    pub after: Vec<SystemLabel>,
+    pub set: Option<SystemSet>,
+    pub conditions: Vec<RunCondition>,
+    pub system: SystemFn,
+    // the change tick this system last ran at, what its `Added` and `Changed` filters compare to
+    pub last_run: AtomicU32,
+    // paused systems are skipped like ones whose run conditions don't hold
+    pub paused: AtomicBool,
+}
+
+impl System {
+    pub fn new(name: &'static str, system: SystemFn, access: SystemAccess) -> System {
+        System {
+            id: SystemId::UNASSIGNED,
+            name,
+            system,
+            access,
+            labels: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            set: None,
+            conditions: Vec::new(),
+            last_run: AtomicU32::new(0),
+            paused: AtomicBool::new(false),
+        }
+    }
+
+    pub fn get_context(&self, this_run: u32) -> SystemContext {
+        SystemContext {
+            id: self.id,
+            name: self.name,
+            last_run: self.last_run.load(Ordering::Relaxed),
+            this_run,
+        }
+    }
+}
+
+tokio::task_local! {
+    static SYSTEM_CONTEXT: SystemContext;
+}
+
+/// Information about the system whose future is currently being polled, set by the `Scheduler`
+#[derive(Debug, Clone, Copy)]
+pub struct SystemContext {
+    // `SystemId::UNASSIGNED` outside of a system
+    pub id: SystemId,
+    pub name: &'static str,
+    pub last_run: u32,
+    pub this_run: u32,
+}
+
+impl SystemContext {
+    pub fn scope<F: futures::Future>(self, future: F) -> impl futures::Future<Output = F::Output> {
+        SYSTEM_CONTEXT.scope(self, future)
+    }
+
+    // `None` outside of a system
+    pub fn try_current() -> Option<SystemContext> {
+        SYSTEM_CONTEXT.try_with(|context| *context).ok()
+    }
+
+    // outside of a system everything that exists counts as added and changed
+    pub fn current(game_state: &GameState) -> SystemContext {
+        Self::try_current().unwrap_or(SystemContext {
+            id: SystemId::UNASSIGNED,
+            name: "<outside of a system>",
+            last_run: 0,
+            this_run: game_state.change_tick(),
+        })
+    }
 }
 
 macro_rules! force_boxed {
@@ -140,22 +450,58 @@ macro_rules! force_boxed {
 }
 pub(crate) use force_boxed;
 
+// `create_system!(render, get_render_system; reads Transform, Map; writes RenderState)`, where
+// `uses` is the same as `writes`. Components and resources are declared the same way
 macro_rules! create_system {
-    ($sys: ident, $getter: ident) => {
+    ($sys: ident, $getter: ident $(; $($access: tt)+)?) => {
         pub fn $getter() -> System {
-            System {
-                system: force_boxed!($sys),
-                args: Vec::new(),
-            }
+            #[allow(unused_mut)]
+            let mut access = SystemAccess::default();
+            $(crate::core::create_system!(@access access; $($access)+);)?
+            System::new(stringify!($sys), force_boxed!($sys), access)
         }
     };
-    ($sys: ident, $getter: ident; uses $($t:ty),+) => {
-        pub fn $getter() -> System {
-            System {
-                system: force_boxed!($sys),
-                args: vec![$(<$t>::get_component_type()),+],
-            }
-        }
+    (@access $access: ident; reads $($t: ty),+ $(; $($rest: tt)+)?) => {
+        $($access.read(<$t>::get_component_type());)+
+        $(crate::core::create_system!(@access $access; $($rest)+);)?
+    };
+    (@access $access: ident; writes $($t: ty),+ $(; $($rest: tt)+)?) => {
+        $($access.write(<$t>::get_component_type());)+
+        $(crate::core::create_system!(@access $access; $($rest)+);)?
+    };
+    (@access $access: ident; uses $($t: ty),+ $(; $($rest: tt)+)?) => {
+        crate::core::create_system!(@access $access; writes $($t),+ $(; $($rest)+)?);
     };
 }
 pub(crate) use create_system;
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Mutex;
+
+    static REPORTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    create_system!(needs_input, get_needs_input; reads Input);
+    async fn needs_input(game_state: &mut GameState, _t: f64, _dt: f64) {
+        if let Err(e) = game_state.get_resource::<Input>() {
+            REPORTS.lock().unwrap().extend(e.report_message());
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_resources_are_reported_once_per_system() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        scheduler
+            .add_system(get_needs_input(), SystemType::Update)
+            .unwrap();
+
+        for _ in 0..3 {
+            scheduler.step(&mut game_state, 0.1).await;
+        }
+        let reports = REPORTS.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].starts_with("needs_input: resource `"));
+    }
+}
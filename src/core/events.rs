@@ -0,0 +1,329 @@
+use crate::core::*;
+use std::collections::HashMap;
+use std::panic::Location;
+use std::sync::Mutex;
+
+// Anything sent between systems, e.g. `PlayerDied(EntityId)`
+pub trait Event: Send + Sync + 'static {}
+impl<T: Send + Sync + 'static> Event for T {}
+
+/// Every `T` sent during the current and the previous frame.
+///
+/// Events are double-buffered so that a reader running before the writer in a frame still sees
+/// them in the next one. Each reader, identified by the system reading and the line it calls
+/// `event_reader` on, keeps its own cursor, so every reader sees every event exactly once as
+/// long as it runs at least once every other frame.
+///
+/// While there are `FixedUpdate` systems, the buffers only move on at the end of frames that ran
+/// a fixed update, so fixed readers see every event too, however many frames pass between two
+/// fixed updates.
+pub struct Events<T: Event> {
+    previous: Vec<T>,
+    current: Vec<T>,
+    // how many events were sent before the first one in `previous`
+    start_count: usize,
+    event_count: usize,
+    cursors: Mutex<HashMap<ReaderId, usize>>,
+}
+
+// Readers outside of systems are told apart by the line alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ReaderId {
+    system: SystemId,
+    location: &'static Location<'static>,
+}
+
+impl<T: Event> Resource for Events<T> {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+impl<T: Event> Default for Events<T> {
+    fn default() -> Self {
+        Events {
+            previous: Vec::new(),
+            current: Vec::new(),
+            start_count: 0,
+            event_count: 0,
+            cursors: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Event> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+        self.event_count += 1;
+    }
+
+    // called by the `Scheduler` once per frame, drops everything sent two frames ago
+    pub fn update(&mut self) {
+        self.start_count += self.previous.len();
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.start_count = self.event_count;
+        self.previous.clear();
+        self.current.clear();
+    }
+
+    // forgets the cursors of a removed or replaced system
+    pub fn remove_readers(&mut self, system: SystemId) {
+        self.cursors
+            .get_mut()
+            .unwrap()
+            .retain(|reader, _| reader.system != system);
+    }
+
+    fn iter_from(&self, cursor: usize) -> impl Iterator<Item = &T> {
+        self.previous
+            .iter()
+            .chain(self.current.iter())
+            .skip(cursor.saturating_sub(self.start_count))
+    }
+
+    // a reader that has never read starts at the oldest event still buffered
+    fn cursor(&self, reader: ReaderId) -> usize {
+        let cursors = self.cursors.lock().unwrap();
+        cursors
+            .get(&reader)
+            .copied()
+            .unwrap_or(0)
+            .max(self.start_count)
+    }
+}
+
+pub struct EventWriter<T: Event> {
+    events: &'static mut Events<T>,
+}
+
+impl<T: Event> EventWriter<T> {
+    pub fn send(&mut self, event: T) {
+        self.events.send(event);
+    }
+
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        for event in events {
+            self.events.send(event);
+        }
+    }
+}
+
+// Only needs `&Events<T>` as the cursors sit behind their own lock
+pub struct EventReader<T: Event> {
+    events: &'static Events<T>,
+    reader: ReaderId,
+}
+
+impl<T: Event> EventReader<T> {
+    // every event this reader has not seen yet, marking them as read
+    pub fn read(&mut self) -> impl Iterator<Item = &'static T> {
+        let cursor = self.events.cursor(self.reader);
+        self.clear();
+        self.events.iter_from(cursor)
+    }
+
+    // how many events `read` would return, without marking them as read
+    pub fn len(&self) -> usize {
+        self.events.event_count - self.events.cursor(self.reader)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.events
+            .cursors
+            .lock()
+            .unwrap()
+            .insert(self.reader, self.events.event_count);
+    }
+}
+
+// What the `Scheduler` does with every `Events<T>` without knowing `T`
+#[derive(Clone, Copy)]
+pub(crate) struct EventType {
+    update: fn(&mut GameState),
+    remove_readers: fn(&mut GameState, SystemId),
+}
+
+fn update_events<T: Event>(game_state: &mut GameState) {
+    if let Ok(events) = game_state.get_resource_mut::<Events<T>>() {
+        events.update();
+    }
+}
+
+fn remove_event_readers<T: Event>(game_state: &mut GameState, system: SystemId) {
+    if let Ok(events) = game_state.get_resource_mut::<Events<T>>() {
+        events.remove_readers(system);
+    }
+}
+
+impl GameState {
+    // inserts the `Events<T>` resource and has the `Scheduler` update it every frame
+    pub fn add_event<T: Event>(&mut self) {
+        if self.contains_resource::<Events<T>>() {
+            return;
+        }
+        self.insert_resource(Events::<T>::default());
+        self.event_types.push(EventType {
+            update: update_events::<T>,
+            remove_readers: remove_event_readers::<T>,
+        });
+    }
+
+    pub fn update_events(&mut self) {
+        for event_type in self.event_types.clone() {
+            (event_type.update)(self);
+        }
+    }
+
+    pub fn remove_event_readers(&mut self, system: SystemId) {
+        for event_type in self.event_types.clone() {
+            (event_type.remove_readers)(self, system);
+        }
+    }
+
+    pub fn send_event<T: Event>(&mut self, event: T) -> Result<(), ResourceError> {
+        self.get_resource_mut::<Events<T>>()?.send(event);
+        Ok(())
+    }
+
+    pub fn event_writer<T: Event>(&mut self) -> Result<EventWriter<T>, ResourceError> {
+        Ok(EventWriter {
+            events: self.get_resource_mut::<Events<T>>()?,
+        })
+    }
+
+    // the reader's cursor belongs to the system this is called from and the calling line, so
+    // a reader in a loop keeps its cursor while two readers next to each other don't share one
+    #[track_caller]
+    pub fn event_reader<T: Event>(&self) -> Result<EventReader<T>, ResourceError> {
+        Ok(EventReader {
+            events: self.get_resource::<Events<T>>()?,
+            reader: ReaderId {
+                system: SystemContext::current(self).id,
+                location: Location::caller(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Hit;
+    static READ: AtomicUsize = AtomicUsize::new(0);
+    static FIXED_READ: AtomicUsize = AtomicUsize::new(0);
+
+    // two systems with the same name, each reading through two readers
+    mod left {
+        use super::*;
+
+        create_system!(read_hits, get_read_hits; reads Events<Hit>);
+        pub async fn read_hits(game_state: &mut GameState, _t: f64, _dt: f64) {
+            let mut first = game_state.event_reader::<Hit>().unwrap();
+            let mut second = game_state.event_reader::<Hit>().unwrap();
+            READ.fetch_add(first.read().count(), Ordering::Relaxed);
+            READ.fetch_add(second.read().count(), Ordering::Relaxed);
+        }
+    }
+
+    mod right {
+        use super::*;
+
+        create_system!(read_hits, get_read_hits; reads Events<Hit>);
+        pub async fn read_hits(game_state: &mut GameState, _t: f64, _dt: f64) {
+            let mut first = game_state.event_reader::<Hit>().unwrap();
+            let mut second = game_state.event_reader::<Hit>().unwrap();
+            READ.fetch_add(first.read().count(), Ordering::Relaxed);
+            READ.fetch_add(second.read().count(), Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn every_reader_has_its_own_cursor() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        game_state.add_event::<Hit>();
+        scheduler
+            .add_system(left::get_read_hits(), SystemType::Update)
+            .unwrap();
+        scheduler
+            .add_system(right::get_read_hits(), SystemType::Update)
+            .unwrap();
+
+        game_state.send_event(Hit).unwrap();
+        // the event is still buffered during the second update, but already read
+        scheduler.step(&mut game_state, 0.1).await;
+        scheduler.step(&mut game_state, 0.1).await;
+        assert_eq!(READ.load(Ordering::Relaxed), 4);
+
+        game_state.send_event(Hit).unwrap();
+        let mut first = game_state.event_reader::<Hit>().unwrap();
+        let mut second = game_state.event_reader::<Hit>().unwrap();
+        assert_eq!(first.read().count(), 1);
+        assert_eq!(second.read().count(), 1);
+        assert!(first.is_empty());
+    }
+
+    create_system!(read_hits_fixed, get_read_hits_fixed; reads Events<Hit>);
+    async fn read_hits_fixed(game_state: &mut GameState, _t: f64, _dt: f64) {
+        let mut reader = game_state.event_reader::<Hit>().unwrap();
+        FIXED_READ.fetch_add(reader.read().count(), Ordering::Relaxed);
+    }
+
+    #[tokio::test]
+    async fn fixed_readers_see_events_sent_between_fixed_updates() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        game_state.add_event::<Hit>();
+        scheduler
+            .add_system(get_read_hits_fixed(), SystemType::FixedUpdate)
+            .unwrap();
+
+        game_state.send_event(Hit).unwrap();
+        // two whole frames without a fixed update
+        scheduler.step(&mut game_state, 0.4).await;
+        scheduler.step(&mut game_state, 0.4).await;
+        assert_eq!(FIXED_READ.load(Ordering::Relaxed), 0);
+
+        scheduler.step(&mut game_state, 0.4).await;
+        scheduler.step(&mut game_state, 1.0).await;
+        assert_eq!(FIXED_READ.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn removed_systems_lose_their_cursors() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        game_state.add_event::<Hit>();
+        let id = scheduler
+            .add_system(left::get_read_hits(), SystemType::Update)
+            .unwrap();
+        let cursors = |game_state: &GameState| {
+            let events = game_state.get_resource::<Events<Hit>>().unwrap();
+            events.cursors.lock().unwrap().len()
+        };
+
+        scheduler.step(&mut game_state, 0.1).await;
+        assert_eq!(cursors(&game_state), 2);
+
+        scheduler.remove_system(id);
+        scheduler.step(&mut game_state, 0.1).await;
+        assert_eq!(cursors(&game_state), 0);
+    }
+}
@@ -1,19 +1,38 @@
 use crate::*;
-use std::any::Any;
+use std::any::TypeId;
 use std::cell::SyncUnsafeCell;
-use std::sync::Arc;
+use std::collections::HashMap;
 
-// increase this every time you add a new component type
-const COMPONENT_TYPES: usize = 2;
+// Pointer back to something that outlives it, like the `GameState`'s `Scheduler`. It can only cross
+// threads when what it points to can, so it doesn't hide anything from `Send` and `Sync` checks
+pub struct SyncPtr<T>(pub *mut T);
+
+unsafe impl<T: Send + Sync> Send for SyncPtr<T> {}
+unsafe impl<T: Send + Sync> Sync for SyncPtr<T> {}
+
+pub struct EntitySlot {
+    pub generation: u32,
+    pub entity: Option<Box<SyncUnsafeCell<Entity>>>,
+}
 
 pub struct GameState {
-    pub entities: Vec<Arc<SyncUnsafeCell<Entity>>>,
-    pub components: Vec<Vec<Arc<SyncUnsafeCell<ComponentStruct>>>>,
-    pub resources: Vec<Box<dyn Resource>>,
+    pub entities: Vec<EntitySlot>,
+    // indexed by `ComponentType`, `None` until the first component of that type is added
+    pub components: Vec<Option<Box<SyncUnsafeCell<dyn ComponentStorage>>>>,
+    pub resources: HashMap<TypeId, Box<dyn Resource>>,
 
-    pub scheduler: *mut Scheduler,
+    pub scheduler: SyncPtr<Scheduler>,
 
-    pub next_entity_id: u32,
+    free_entities: Vec<u32>,
+    change_tick: u32,
+    command_queue: CommandQueue,
+    pub(crate) event_types: Vec<EventType>,
+    pub(crate) state_transitions: Vec<fn(&mut GameState) -> Option<StateTransition>>,
+    pub(crate) save_registry: SaveRegistry,
+    pub(crate) prefab_cache: PrefabCache,
+    pub(crate) lifecycles: ComponentLifecycles,
+    pub(crate) name_index: NameIndex,
+    pub borrow_tracker: BorrowTracker,
     pub conf: &'static Config,
 
     pub should_close: bool,
@@ -25,133 +44,373 @@ impl GameState {
     }
 
     pub fn get_scheduler(&self) -> &Scheduler {
-        unsafe { &*self.scheduler }
+        unsafe { &*self.scheduler.0 }
     }
 
     pub fn get_scheduler_mut(&mut self) -> &mut Scheduler {
-        unsafe { &mut *self.scheduler }
+        unsafe { &mut *self.scheduler.0 }
     }
 
-    pub const fn get_component_type() -> ComponentType {
-        COMPONENT_TYPES
+    // a system writing `GameState` gets exclusive access to everything, one reading it can read
+    // everything
+    pub fn get_component_type() -> ComponentType {
+        cached_component_type!()
+    }
+
+    // for headless runs and tests that don't load `CONFIG` from the resources directory. The
+    // config is leaked, like `CONFIG` lives for the whole program
+    pub fn with_config(scheduler: *mut Scheduler, conf: Config) -> GameState {
+        Self::new(scheduler, Box::leak(Box::new(conf)))
     }
 
     pub fn new(scheduler: *mut Scheduler, conf: &'static Config) -> GameState {
-        GameState {
+        let mut game_state = GameState {
             entities: Vec::new(),
-            components: vec![Vec::new(); COMPONENT_TYPES],
-            resources: Vec::new(),
-            next_entity_id: 0,
-            scheduler,
+            components: Vec::new(),
+            resources: HashMap::new(),
+            scheduler: SyncPtr(scheduler),
+            free_entities: Vec::new(),
+            // 0 is the tick systems that have never run start from, so nothing may be stamped with it
+            change_tick: 1,
+            command_queue: CommandQueue::default(),
+            event_types: Vec::new(),
+            state_transitions: Vec::new(),
+            save_registry: SaveRegistry::default(),
+            prefab_cache: PrefabCache::default(),
+            lifecycles: ComponentLifecycles::default(),
+            name_index: NameIndex::default(),
+            borrow_tracker: BorrowTracker::default(),
 
             conf,
             should_close: false,
+        };
+
+        game_state.register_saveable::<Parent>();
+        game_state.register_saveable::<Children>();
+
+        // the scheduler outlives the game state
+        let scheduler = unsafe { &*game_state.scheduler.0 };
+        game_state.insert_resource(Time::new(scheduler));
+        game_state.insert_resource(FixedTime::new(scheduler.fixed_update_interval()));
+
+        game_state
+    }
+
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    // called by the `Scheduler` after each execution group
+    pub fn increment_change_tick(&mut self) -> u32 {
+        self.change_tick = self.change_tick.wrapping_add(1);
+        if self.change_tick == 0 {
+            self.change_tick = 1;
+        }
+        self.change_tick
+    }
+
+    pub fn commands(&self) -> Commands<'_> {
+        Commands::new(&self.command_queue)
+    }
+
+    // commands may queue further commands, which are applied in the same call
+    pub fn apply_commands(&mut self) {
+        loop {
+            let commands = self.command_queue.take();
+            if commands.is_empty() {
+                break;
+            }
+            for command in commands {
+                command(self);
+            }
         }
     }
 
     pub fn create_entity<'a>(&mut self, name: String) -> &'a mut Entity {
-        let entity = Entity::new(self.next_entity_id, name);
-        let rc = Arc::new(SyncUnsafeCell::new(entity));
+        let id = match self.free_entities.pop() {
+            Some(index) => EntityId {
+                index,
+                generation: self.entities[index as usize].generation,
+            },
+            None => {
+                self.entities.push(EntitySlot {
+                    generation: 0,
+                    entity: None,
+                });
+                EntityId {
+                    index: self.entities.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        };
 
-        self.entities.push(rc.clone());
-        self.next_entity_id += 1;
+        self.name_index.insert(&name, id);
+        let entity = Box::new(SyncUnsafeCell::new(Entity::new(id, name)));
+        let entity = self.entities[id.index as usize].entity.insert(entity);
 
-        unsafe { &mut *rc.get() }
+        unsafe { &mut *entity.get() }
     }
 
-    pub fn get_entity(&self, id: usize) -> Option<&'static mut Entity> {
-        if id >= self.entities.len() {
+    // for restoring saved entities, returns `None` if the slot is already taken. Handles from
+    // before the slot was last freed may become valid again, so only use this on a fresh world
+    pub fn create_entity_with_id<'a>(
+        &mut self,
+        id: EntityId,
+        name: String,
+    ) -> Option<&'a mut Entity> {
+        let index = id.index as usize;
+        while self.entities.len() <= index {
+            self.free_entities.push(self.entities.len() as u32);
+            self.entities.push(EntitySlot {
+                generation: 0,
+                entity: None,
+            });
+        }
+        if self.entities[index].entity.is_some() {
             return None;
         }
-        Some(unsafe { &mut *self.entities[id].get() })
+
+        self.free_entities.retain(|free| *free != id.index);
+        self.entities[index].generation = id.generation;
+
+        self.name_index.insert(&name, id);
+        let entity = Box::new(SyncUnsafeCell::new(Entity::new(id, name)));
+        let entity = self.entities[index].entity.insert(entity);
+
+        Some(unsafe { &mut *entity.get() })
+    }
+
+    // returns false if the entity was already despawned. Inside a system, an entity with
+    // components that have `Remove` hooks or observers is only despawned once the execution
+    // group is done, so they can still read them
+    pub fn despawn_entity(&mut self, id: EntityId) -> bool {
+        if !self.is_alive(id) {
+            return false;
+        }
+
+        let components = self.get_entity(id).unwrap().components.clone();
+        let deferred = components
+            .iter()
+            .any(|component_type| self.defers_lifecycle(Lifecycle::Remove, *component_type));
+        if deferred {
+            self.commands().add(move |game_state| {
+                game_state.despawn_entity(id);
+            });
+            return true;
+        }
+
+        self.detach_from_hierarchy(id);
+
+        for component_type in components {
+            // an earlier hook may have removed it, or despawned the entity
+            let present = self
+                .get_entity(id)
+                .is_some_and(|entity| entity.has_component(component_type));
+            if present {
+                self.trigger_lifecycle(Lifecycle::Remove, component_type, id);
+            }
+        }
+        // a hook may have despawned it already
+        if !self.is_alive(id) {
+            return true;
+        }
+
+        let slot = &mut self.entities[id.index as usize];
+        let entity = slot.entity.take().unwrap();
+        // bumping the generation invalidates every outstanding handle to this slot
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_entities.push(id.index);
+
+        let entity = entity.into_inner();
+        self.name_index.remove(entity.name(), id);
+        for component_type in &entity.components {
+            if let Some(storage) = self.get_storage_by_type_mut(*component_type) {
+                storage.remove(id);
+            }
+        }
+
+        true
+    }
+
+    pub fn is_alive(&self, id: EntityId) -> bool {
+        self.entities
+            .get(id.index as usize)
+            .is_some_and(|slot| slot.generation == id.generation && slot.entity.is_some())
     }
 
-    pub fn get_entity_mut(&self, id: usize) -> Option<&'static mut Entity> {
-        if id >= self.entities.len() {
+    pub fn get_entity(&self, id: EntityId) -> Option<&'static mut Entity> {
+        if !self.is_alive(id) {
             return None;
         }
-        Some(unsafe { &mut *self.entities[id].get() })
+        let entity = self.entities[id.index as usize].entity.as_ref()?;
+        Some(unsafe { &mut *entity.get() })
+    }
+
+    pub fn get_entity_mut(&self, id: EntityId) -> Option<&'static mut Entity> {
+        self.get_entity(id)
     }
 
-    pub fn get_entities_with<T: Component>(
+    pub fn get_storage_by_type(
         &self,
         component_type: ComponentType,
-    ) -> Vec<&'static Entity> {
-        self.components[component_type]
-            .iter()
-            .map(|component| {
-                let component = component.get();
-                let entity = unsafe { &*component }.owner as usize;
-                unsafe { &*self.entities[entity].get() }
-            })
-            .collect()
+    ) -> Option<&'static dyn ComponentStorage> {
+        let storage = self.components.get(component_type)?.as_ref()?;
+        Some(unsafe { &*storage.get() })
     }
 
-    pub fn get_entities_with_mut<T: Component>(
-        &mut self,
+    pub fn get_storage_by_type_mut(
+        &self,
         component_type: ComponentType,
-    ) -> Vec<&'static mut Entity> {
-        self.components[component_type]
-            .iter_mut()
-            .map(|component| {
-                let component = component.get();
-                let entity = unsafe { &*component }.owner as usize;
-                unsafe { &mut *self.entities[entity].get() }
-            })
-            .collect()
+    ) -> Option<&'static mut dyn ComponentStorage> {
+        let storage = self.components.get(component_type)?.as_ref()?;
+        Some(unsafe { &mut *storage.get() })
+    }
+
+    pub fn get_storage<T: Component>(&self) -> Option<&'static SparseSet<T>> {
+        self.borrow_tracker.borrow_component::<T>(false);
+        let storage = self.get_storage_by_type(T::get_component_type())?;
+        (storage as &dyn Any).downcast_ref()
     }
 
-    pub fn get_components<T: Component>(&self, component_type: ComponentType) -> Vec<&'static T> {
+    pub fn get_storage_mut<T: Component>(&self) -> Option<&'static mut SparseSet<T>> {
+        self.borrow_tracker.borrow_component::<T>(true);
+        let storage = self.get_storage_by_type_mut(T::get_component_type())?;
+        (storage as &mut dyn Any).downcast_mut()
+    }
+
+    pub fn get_storage_or_insert<T: Component>(&mut self) -> &'static mut SparseSet<T> {
+        let component_type = T::get_component_type();
+        if self.components.len() <= component_type {
+            self.components.resize_with(component_type + 1, || None);
+        }
         self.components[component_type]
+            .get_or_insert_with(|| Box::new(SyncUnsafeCell::new(SparseSet::<T>::new())));
+
+        self.get_storage_mut::<T>().unwrap()
+    }
+
+    pub fn has_component(&self, entity: EntityId, component_type: ComponentType) -> bool {
+        self.get_storage_by_type(component_type)
+            .is_some_and(|storage| storage.contains(entity))
+    }
+
+    pub fn get_component<T: Component>(&self, entity: EntityId) -> Option<&'static T> {
+        self.get_storage::<T>()?.get(entity)
+    }
+
+    // marks the component as changed straight away, use a query for `Mut` to only mark on write
+    pub fn get_component_mut<T: Component>(&self, entity: EntityId) -> Option<&'static mut T> {
+        let (component, ticks) = self.get_storage_mut::<T>()?.get_with_ticks_mut(entity)?;
+        ticks.changed = SystemContext::current(self).this_run;
+        Some(component)
+    }
+
+    pub fn remove_component<T: Component>(&mut self, entity: EntityId) -> Option<T> {
+        self.get_entity_mut(entity)?.remove_component::<T>(self)
+    }
+
+    // empty for types that have never had a component added
+    fn entities_of<T: Component>(&self) -> &'static [EntityId] {
+        self.get_storage::<T>()
+            .map_or(&[], |storage| storage.entities())
+    }
+
+    pub fn get_entities_with<T: Component>(&self) -> Vec<&'static Entity> {
+        self.entities_of::<T>()
             .iter()
-            .map(|component| {
-                let component = component.get();
-                let component = unsafe { &*component };
-                let component = &component.component;
-                let component = &**component;
-                unsafe { &*((component as &dyn Any).downcast_ref_unchecked::<T>() as *const T) }
-            })
+            .filter_map(|entity| self.get_entity(*entity))
+            .map(|entity| &*entity)
             .collect()
     }
 
-    pub fn get_components_mut<T: Component>(
-        &mut self,
-        component_type: ComponentType,
-    ) -> Vec<&'static mut T> {
-        self.components[component_type]
-            .iter_mut()
-            .map(|component| {
-                let component = component.get();
-                let component = unsafe { &mut *component };
-                let component = &mut component.component;
-                let component = &mut **component;
-                let component =
-                    unsafe { (component as &mut dyn std::any::Any).downcast_mut_unchecked::<T>() };
-                let component = component as *mut T;
-                unsafe { &mut *component }
-            })
+    pub fn get_entities_with_mut<T: Component>(&mut self) -> Vec<&'static mut Entity> {
+        self.entities_of::<T>()
+            .iter()
+            .filter_map(|entity| self.get_entity(*entity))
             .collect()
     }
 
-    pub fn add_resource<T: Resource>(&mut self, resource: T) {
-        self.resources.push(Box::new(resource));
+    pub fn query<D: ReadOnlyQueryData, F: QueryFilter>(&self) -> Query<'_, D, F> {
+        Query::new(self)
     }
 
-    pub fn get_resource<T: Resource>(&self) -> Option<&'static T> {
-        for resource in &self.resources {
-            if let Some(r) = resource.as_ref().as_any().downcast_ref::<T>() {
-                return Some(unsafe { &*(r as *const _) });
-            }
-        }
-        None
+    // borrows the whole `GameState` so nothing else can reach the components it writes
+    pub fn query_mut<D: QueryData, F: QueryFilter>(&mut self) -> Query<'_, D, F> {
+        Query::new_mut(self)
     }
 
-    pub fn get_resource_mut<T: Resource>(&mut self) -> Option<&'static mut T> {
-        for resource in &mut self.resources {
-            if let Some(r) = (resource.as_mut() as &mut dyn std::any::Any).downcast_mut::<T>() {
-                return Some(unsafe { &mut *(r as *mut _) });
-            }
+    pub fn get_components<T: Component>(&self) -> Vec<&'static T> {
+        self.get_storage::<T>()
+            .map_or(Vec::new(), |storage| storage.values().iter().collect())
+    }
+
+    // marks every `T` as changed
+    pub fn get_components_mut<T: Component>(&mut self) -> Vec<&'static mut T> {
+        let Some(storage) = self.get_storage_mut::<T>() else {
+            return Vec::new();
+        };
+
+        let this_run = SystemContext::current(self).this_run;
+        for ticks in storage.ticks_mut() {
+            ticks.changed = this_run;
         }
-        None
+        storage.values_mut().iter_mut().collect()
+    }
+
+    // returns the previous value if there was one
+    pub fn insert_resource<T: Resource>(&mut self, resource: T) -> Option<T> {
+        self.borrow_tracker.borrow_resource::<T>(true);
+        let previous = self
+            .resources
+            .insert(TypeId::of::<T>(), Box::new(resource))?;
+        Some(*(previous as Box<dyn Any>).downcast::<T>().unwrap())
+    }
+
+    pub fn remove_resource<T: Resource>(&mut self) -> Option<T> {
+        self.borrow_tracker.borrow_resource::<T>(true);
+        let resource = self.resources.remove(&TypeId::of::<T>())?;
+        Some(*(resource as Box<dyn Any>).downcast::<T>().unwrap())
+    }
+
+    pub fn contains_resource<T: Resource>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn get_resource<T: Resource>(&self) -> Result<&'static T, ResourceError> {
+        self.borrow_tracker.borrow_resource::<T>(false);
+        let resource = self
+            .resources
+            .get(&TypeId::of::<T>())
+            .ok_or_else(ResourceError::missing::<T>)?;
+        let resource = resource.as_any().downcast_ref::<T>().unwrap();
+
+        Ok(unsafe { &*(resource as *const T) })
+    }
+
+    pub fn get_resource_mut<T: Resource>(&mut self) -> Result<&'static mut T, ResourceError> {
+        self.borrow_tracker.borrow_resource::<T>(true);
+        let resource = self
+            .resources
+            .get_mut(&TypeId::of::<T>())
+            .ok_or_else(ResourceError::missing::<T>)?;
+        let resource = (resource.as_mut() as &mut dyn Any)
+            .downcast_mut::<T>()
+            .unwrap();
+
+        Ok(unsafe { &mut *(resource as *mut T) })
+    }
+
+    // takes `T` out for the duration of `f`, so `f` can use it alongside the rest of the game state
+    pub fn resource_scope<T: Resource, R>(
+        &mut self,
+        f: impl FnOnce(&mut GameState, &mut T) -> R,
+    ) -> Result<R, ResourceError> {
+        let mut resource = self
+            .remove_resource::<T>()
+            .ok_or_else(ResourceError::missing::<T>)?;
+        let result = f(self, &mut resource);
+        self.insert_resource(resource);
+
+        Ok(result)
     }
 }
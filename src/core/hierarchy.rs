@@ -0,0 +1,94 @@
+use crate::core::*;
+use serde::{Deserialize, Serialize};
+
+/// The entity this entity is attached to, kept in sync with that entity's `Children`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Parent(pub EntityId);
+impl_component!(Parent);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Children(pub Vec<EntityId>);
+impl_component!(Children);
+
+impl GameState {
+    // returns false if either entity is dead or `parent` is `child` or one of its descendants
+    pub fn set_parent(&mut self, child: EntityId, parent: EntityId) -> bool {
+        if !self.is_alive(child) || !self.is_alive(parent) {
+            return false;
+        }
+
+        let mut ancestor = Some(parent);
+        while let Some(current) = ancestor {
+            if current == child {
+                return false;
+            }
+            ancestor = self.get_component::<Parent>(current).map(|parent| parent.0);
+        }
+
+        self.remove_parent(child);
+
+        match self.get_component_mut::<Children>(parent) {
+            Some(children) => children.0.push(child),
+            None => self
+                .get_entity_mut(parent)
+                .unwrap()
+                .add_component(self, Children(vec![child])),
+        }
+        self.get_entity_mut(child)
+            .unwrap()
+            .add_component(self, Parent(parent));
+
+        true
+    }
+
+    // detaches `child` from its parent, leaving it as a root
+    pub fn remove_parent(&mut self, child: EntityId) -> Option<EntityId> {
+        let Parent(parent) = self.remove_component::<Parent>(child)?;
+
+        if let Some(children) = self.get_component_mut::<Children>(parent) {
+            children.0.retain(|other| *other != child);
+        }
+
+        Some(parent)
+    }
+
+    // every descendant of `entity`, parents before their children
+    pub fn get_descendants(&self, entity: EntityId) -> Vec<EntityId> {
+        let mut descendants = Vec::new();
+        let mut stack = vec![entity];
+
+        while let Some(current) = stack.pop() {
+            if let Some(children) = self.get_component::<Children>(current) {
+                descendants.extend_from_slice(&children.0);
+                stack.extend(children.0.iter().rev());
+            }
+        }
+
+        descendants
+    }
+
+    pub fn despawn_recursive(&mut self, entity: EntityId) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        self.remove_parent(entity);
+        for descendant in self.get_descendants(entity).into_iter().rev() {
+            self.despawn_entity(descendant);
+        }
+
+        self.despawn_entity(entity)
+    }
+
+    // called when an entity is despawned on its own, so no handle is left dangling: the parent
+    // forgets it and its children become roots
+    pub(crate) fn detach_from_hierarchy(&mut self, entity: EntityId) {
+        self.remove_parent(entity);
+
+        if let Some(Children(children)) = self.remove_component::<Children>(entity) {
+            for child in children {
+                self.remove_component::<Parent>(child);
+            }
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::core::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub type ComponentHook = fn(&mut GameState, EntityId);
+pub type Observer = Arc<dyn Fn(&mut GameState, EntityId) + Send + Sync>;
+
+/// The points in a component's life that hooks and observers can react to.
+///
+/// `Add` fires when an entity gets a component it didn't have, `Insert` every time a component
+/// is written with `add_component`, replacing or not, and `Remove` right before a component is
+/// removed or its entity despawned, so it can still be read.
+///
+/// Changes made from inside a system only fire them once its execution group is done, like
+/// commands, as the rest of the group may still be running. Removals and despawns that fire
+/// `Remove` are deferred along with it, so the component can still be read then too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lifecycle {
+    Add,
+    Insert,
+    Remove,
+}
+
+// Hooks belong to the component type itself, so there is at most one of each and they always
+// run before any observers
+#[derive(Default, Clone, Copy)]
+pub struct ComponentHooks {
+    on_add: Option<ComponentHook>,
+    on_insert: Option<ComponentHook>,
+    on_remove: Option<ComponentHook>,
+}
+
+impl ComponentHooks {
+    pub fn on_add(&mut self, hook: ComponentHook) -> &mut Self {
+        assert!(self.on_add.is_none(), "`on_add` hook is already registered");
+        self.on_add = Some(hook);
+        self
+    }
+
+    pub fn on_insert(&mut self, hook: ComponentHook) -> &mut Self {
+        assert!(
+            self.on_insert.is_none(),
+            "`on_insert` hook is already registered"
+        );
+        self.on_insert = Some(hook);
+        self
+    }
+
+    pub fn on_remove(&mut self, hook: ComponentHook) -> &mut Self {
+        assert!(
+            self.on_remove.is_none(),
+            "`on_remove` hook is already registered"
+        );
+        self.on_remove = Some(hook);
+        self
+    }
+
+    fn get(&self, lifecycle: Lifecycle) -> Option<ComponentHook> {
+        match lifecycle {
+            Lifecycle::Add => self.on_add,
+            Lifecycle::Insert => self.on_insert,
+            Lifecycle::Remove => self.on_remove,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+#[derive(Default)]
+pub struct ComponentLifecycles {
+    hooks: HashMap<ComponentType, ComponentHooks>,
+    observers: HashMap<(ComponentType, Lifecycle), Vec<(ObserverId, Observer)>>,
+    next_observer: u64,
+}
+
+impl GameState {
+    // e.g. `game_state.register_component_hooks::<Sprite>().on_add(..).on_remove(..)`
+    pub fn register_component_hooks<T: Component>(&mut self) -> &mut ComponentHooks {
+        self.lifecycles
+            .hooks
+            .entry(T::get_component_type())
+            .or_default()
+    }
+
+    pub fn observe<T: Component>(
+        &mut self,
+        lifecycle: Lifecycle,
+        observer: impl Fn(&mut GameState, EntityId) + Send + Sync + 'static,
+    ) -> ObserverId {
+        let lifecycles = &mut self.lifecycles;
+        let id = ObserverId(lifecycles.next_observer);
+        lifecycles.next_observer += 1;
+
+        lifecycles
+            .observers
+            .entry((T::get_component_type(), lifecycle))
+            .or_default()
+            .push((id, Arc::new(observer)));
+        id
+    }
+
+    // returns false if the observer was already removed
+    pub fn remove_observer(&mut self, id: ObserverId) -> bool {
+        for observers in self.lifecycles.observers.values_mut() {
+            if let Some(index) = observers.iter().position(|(other, _)| *other == id) {
+                observers.remove(index);
+                return true;
+            }
+        }
+        false
+    }
+
+    // called by `Entity` and `despawn_entity`, so it covers direct calls and commands alike
+    pub(crate) fn trigger_lifecycle(
+        &mut self,
+        lifecycle: Lifecycle,
+        component_type: ComponentType,
+        entity: EntityId,
+    ) {
+        if SystemContext::try_current().is_none() {
+            return self.run_lifecycle(lifecycle, component_type, entity);
+        }
+        if self.defers_lifecycle(lifecycle, component_type) {
+            self.commands().add(move |game_state| {
+                game_state.run_lifecycle(lifecycle, component_type, entity);
+            });
+        }
+    }
+
+    // hooks and observers get `&mut GameState`, which nothing may have while other systems of
+    // the group are running
+    pub(crate) fn defers_lifecycle(
+        &self,
+        lifecycle: Lifecycle,
+        component_type: ComponentType,
+    ) -> bool {
+        SystemContext::try_current().is_some()
+            && self.has_lifecycle_reactions(lifecycle, component_type)
+    }
+
+    fn has_lifecycle_reactions(&self, lifecycle: Lifecycle, component_type: ComponentType) -> bool {
+        let lifecycles = &self.lifecycles;
+        lifecycles
+            .hooks
+            .get(&component_type)
+            .is_some_and(|hooks| hooks.get(lifecycle).is_some())
+            || lifecycles
+                .observers
+                .get(&(component_type, lifecycle))
+                .is_some_and(|observers| !observers.is_empty())
+    }
+
+    fn run_lifecycle(
+        &mut self,
+        lifecycle: Lifecycle,
+        component_type: ComponentType,
+        entity: EntityId,
+    ) {
+        let hook = self
+            .lifecycles
+            .hooks
+            .get(&component_type)
+            .and_then(|hooks| hooks.get(lifecycle));
+        if let Some(hook) = hook {
+            hook(self, entity);
+        }
+
+        // cloned so observers can register or remove observers themselves
+        let Some(observers) = self.lifecycles.observers.get(&(component_type, lifecycle)) else {
+            return;
+        };
+        for (_, observer) in observers.clone() {
+            observer(self, entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    static ADDED: AtomicUsize = AtomicUsize::new(0);
+    static ADDED_DURING_SYSTEM: AtomicUsize = AtomicUsize::new(usize::MAX);
+    static REMOVED_POSITIONS: Mutex<Vec<[f32; 2]>> = Mutex::new(Vec::new());
+
+    create_system!(make_players, get_make_players; writes Player);
+    async fn make_players(game_state: &mut GameState, _t: f64, _dt: f64) {
+        let entity = game_state.create_entity("player".to_string());
+        entity.add_component(game_state, Player);
+        ADDED_DURING_SYSTEM.store(ADDED.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    #[tokio::test]
+    async fn observers_of_changes_made_in_systems_run_after_the_group() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        game_state.observe::<Player>(Lifecycle::Add, |_, _| {
+            ADDED.fetch_add(1, Ordering::Relaxed);
+        });
+        scheduler
+            .add_system(get_make_players(), SystemType::Update)
+            .unwrap();
+
+        scheduler.step(&mut game_state, 0.1).await;
+        assert_eq!(ADDED_DURING_SYSTEM.load(Ordering::Relaxed), 0);
+        assert_eq!(ADDED.load(Ordering::Relaxed), 1);
+    }
+
+    fn drop_player(game_state: &mut GameState, entity: EntityId) {
+        game_state
+            .get_entity_mut(entity)
+            .unwrap()
+            .remove_component::<Player>(game_state);
+    }
+
+    #[tokio::test]
+    async fn despawning_skips_components_removed_by_hooks() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        game_state
+            .register_component_hooks::<Transform>()
+            .on_remove(drop_player);
+        let removed = Arc::new(AtomicUsize::new(0));
+        let observed = removed.clone();
+        game_state.observe::<Player>(Lifecycle::Remove, move |_, _| {
+            observed.fetch_add(1, Ordering::Relaxed);
+        });
+
+        let transform = Transform {
+            position: [0.0, 0.0],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+        };
+        let entity = game_state.create_entity("player".to_string());
+        entity.add_component(&mut game_state, transform);
+        entity.add_component(&mut game_state, Player);
+        let id = entity.id;
+
+        assert!(game_state.despawn_entity(id));
+        assert_eq!(removed.load(Ordering::Relaxed), 1);
+    }
+
+    create_system!(drop_transforms, get_drop_transforms; writes Transform);
+    async fn drop_transforms(game_state: &mut GameState, _t: f64, _dt: f64) {
+        let entities: Vec<EntityId> = game_state
+            .query::<EntityId, With<Transform>>()
+            .iter()
+            .collect();
+        for entity in entities {
+            assert!(game_state.remove_component::<Transform>(entity).is_none());
+        }
+    }
+
+    fn read_removed_transform(game_state: &mut GameState, entity: EntityId) {
+        let transform = game_state.get_component::<Transform>(entity).unwrap();
+        REMOVED_POSITIONS.lock().unwrap().push(transform.position);
+    }
+
+    #[tokio::test]
+    async fn components_removed_in_systems_can_be_read_on_remove() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        game_state
+            .register_component_hooks::<Transform>()
+            .on_remove(read_removed_transform);
+        scheduler
+            .add_system(get_drop_transforms(), SystemType::Update)
+            .unwrap();
+
+        let transform = Transform {
+            position: [1.0, 2.0],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+        };
+        let entity = game_state.create_entity("dropped".to_string());
+        entity.add_component(&mut game_state, transform);
+        let id = entity.id;
+
+        scheduler.step(&mut game_state, 0.1).await;
+        assert_eq!(*REMOVED_POSITIONS.lock().unwrap(), [[1.0, 2.0]]);
+        assert!(game_state.get_component::<Transform>(id).is_none());
+        assert!(!game_state.has_component(id, Transform::get_component_type()));
+    }
+}
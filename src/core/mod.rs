@@ -4,8 +4,59 @@ pub use game_state::*;
 pub mod scheduler;
 pub use scheduler::*;
 
+pub mod schedule;
+pub use schedule::*;
+
+pub mod state;
+pub use state::*;
+
+pub mod profiler;
+pub use profiler::*;
+
+pub mod time;
+pub use time::*;
+
+pub mod storage;
+pub use storage::*;
+
 pub mod ecs;
 pub use ecs::*;
 
+pub mod query;
+pub use query::*;
+
+pub mod change_detection;
+pub use change_detection::*;
+
+pub mod type_registry;
+pub use type_registry::*;
+
+pub mod commands;
+pub use commands::*;
+
+pub mod events;
+pub use events::*;
+
+pub mod save;
+pub use save::*;
+
+pub mod prefab;
+pub use prefab::*;
+
+pub mod names;
+pub use names::*;
+
+pub mod hooks;
+pub use hooks::*;
+
+pub mod borrow_tracker;
+pub use borrow_tracker::*;
+
+pub mod hierarchy;
+pub use hierarchy::*;
+
 pub mod resources;
 pub use resources::*;
+
+#[cfg(test)]
+mod benches;
@@ -0,0 +1,94 @@
+use crate::core::*;
+use std::collections::HashMap;
+
+// Entities by `Entity::name`, in the order they got that name. Names don't have to be unique
+#[derive(Default)]
+pub struct NameIndex {
+    entities: HashMap<String, Vec<EntityId>>,
+}
+
+impl NameIndex {
+    pub(crate) fn insert(&mut self, name: &str, entity: EntityId) {
+        self.entities
+            .entry(name.to_string())
+            .or_default()
+            .push(entity);
+    }
+
+    pub(crate) fn remove(&mut self, name: &str, entity: EntityId) {
+        let Some(entities) = self.entities.get_mut(name) else {
+            return;
+        };
+        entities.retain(|other| *other != entity);
+        if entities.is_empty() {
+            self.entities.remove(name);
+        }
+    }
+}
+
+impl GameState {
+    // the oldest entity with this name, for names meant to be unique like "Player"
+    pub fn find_entity_by_name(&self, name: &str) -> Option<EntityId> {
+        self.entities_named(name).first().copied()
+    }
+
+    // inside a system this needs `reads GameState`, as renames write the index
+    pub fn entities_named(&self, name: &str) -> &[EntityId] {
+        self.borrow_tracker.borrow_game_state(false);
+        self.name_index
+            .entities
+            .get(name)
+            .map_or(&[], |entities| entities.as_slice())
+    }
+
+    // returns false if the entity was already despawned
+    pub fn rename_entity(&mut self, entity: EntityId, name: impl Into<String>) -> bool {
+        match self.get_entity_mut(entity) {
+            Some(entity) => {
+                entity.set_name(self, name);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    create_system!(rename_players, get_rename_players; reads Player);
+    async fn rename_players(game_state: &mut GameState, _t: f64, _dt: f64) {
+        let players: Vec<EntityId> = game_state
+            .query::<EntityId, With<Player>>()
+            .iter()
+            .collect();
+        for player in players {
+            game_state.rename_entity(player, "Renamed");
+        }
+    }
+
+    create_system!(count_players, get_count_players; reads GameState);
+    async fn count_players(game_state: &mut GameState, _t: f64, _dt: f64) {
+        assert_eq!(game_state.entities_named("Player").len(), 1);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "system `rename_players` borrows \
+                               `klaus_of_death::core::game_state::GameState` mutably without \
+                               declaring it in `writes`")]
+    async fn renaming_in_a_system_needs_game_state_access() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        let entity = game_state.create_entity("Player".to_string());
+        entity.add_component(&mut game_state, Player);
+        scheduler
+            .add_system(get_count_players(), SystemType::Update)
+            .unwrap();
+        scheduler
+            .add_system(get_rename_players(), SystemType::Update)
+            .unwrap();
+
+        scheduler.step(&mut game_state, 0.1).await;
+    }
+}
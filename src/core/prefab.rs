@@ -0,0 +1,195 @@
+use crate::core::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// An entity template from `kod_resources/prefabs/<path>.ron`, for example:
+///
+/// ```ron
+/// (
+///     name: "Imp",
+///     components: {
+///         "Transform": (position: (0.0, 0.0), rotation: 0.0, scale: (1.0, 1.0)),
+///     },
+/// )
+/// ```
+///
+/// Components are keyed by type name like in save files, so only types registered with
+/// `register_saveable` can be used.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Prefab {
+    pub name: String,
+    pub components: BTreeMap<String, ron::Value>,
+}
+
+#[derive(Debug)]
+pub enum PrefabError {
+    Missing(String, std::io::Error),
+    Invalid(String, SaveError),
+}
+
+impl std::fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PrefabError::Missing(path, e) => write!(f, "prefab `{path}` can't be read: {e}"),
+            PrefabError::Invalid(path, e) => write!(f, "prefab `{path}` is invalid: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for PrefabError {}
+
+/// Components replacing the prefab's, or added to them, for a single spawn:
+/// `PrefabOverrides::default().with(Transform { position: [3.0, 4.0], ..transform })`
+#[derive(Default)]
+pub struct PrefabOverrides {
+    // keyed by type name like the prefab's components, but they don't have to be saveable
+    components: Vec<(String, StagedComponent)>,
+}
+
+impl PrefabOverrides {
+    // overriding the same component type again replaces the earlier override
+    pub fn with<T: Component>(mut self, component: T) -> Self {
+        let name = short_type_name(std::any::type_name::<T>());
+        self.components.retain(|(other, _)| *other != name);
+        self.components.push((
+            name,
+            Box::new(move |game_state, entity| {
+                if let Some(entity) = game_state.get_entity_mut(entity) {
+                    entity.add_component(game_state, component);
+                }
+            }),
+        ));
+        self
+    }
+
+    fn overrides(&self, name: &str) -> bool {
+        self.components.iter().any(|(other, _)| other == name)
+    }
+}
+
+// Prefabs are read once and kept, `reload_prefabs` picks up edited files
+#[derive(Default)]
+pub struct PrefabCache {
+    prefabs: HashMap<String, Prefab>,
+}
+
+fn read_prefab(path: &str) -> Result<Prefab, PrefabError> {
+    let file = format!("{}/prefabs/{path}.ron", *RESOURCES_DIR);
+    let data =
+        std::fs::read_to_string(file).map_err(|e| PrefabError::Missing(path.to_string(), e))?;
+    ron::from_str(&data).map_err(|e| PrefabError::Invalid(path.to_string(), e.into()))
+}
+
+impl GameState {
+    pub fn get_prefab(&mut self, path: &str) -> Result<&Prefab, PrefabError> {
+        if !self.prefab_cache.prefabs.contains_key(path) {
+            let prefab = read_prefab(path)?;
+            self.prefab_cache.prefabs.insert(path.to_string(), prefab);
+        }
+        Ok(&self.prefab_cache.prefabs[path])
+    }
+
+    pub fn reload_prefabs(&mut self) {
+        self.prefab_cache.prefabs.clear();
+    }
+
+    // overridden components are never added with the prefab's values, so their hooks only see
+    // the overrides. Nothing is spawned if the prefab is invalid
+    pub fn spawn_prefab<'a>(
+        &mut self,
+        path: &str,
+        overrides: PrefabOverrides,
+    ) -> Result<&'a mut Entity, PrefabError> {
+        let prefab = self.get_prefab(path)?.clone();
+        let mut components = Vec::with_capacity(prefab.components.len());
+        for (name, value) in prefab.components {
+            if overrides.overrides(&name) {
+                continue;
+            }
+            let component = self
+                .stage_component(name, value)
+                .map_err(|e| PrefabError::Invalid(path.to_string(), e))?;
+            components.push(component);
+        }
+
+        let entity = self.create_entity(prefab.name);
+        let id = entity.id;
+        let overrides = overrides
+            .components
+            .into_iter()
+            .map(|(_, component)| component);
+        for component in components.into_iter().chain(overrides) {
+            component(self, id);
+        }
+
+        Ok(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn transform(x: f32, y: f32) -> Transform {
+        Transform {
+            position: [x, y],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+        }
+    }
+
+    #[tokio::test]
+    async fn overrides_replace_prefab_components() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        game_state.register_saveable::<Transform>();
+        game_state.register_saveable::<Player>();
+        let components = [
+            (
+                "Transform",
+                "(position: (1.0, 1.0), rotation: 0.0, scale: (1.0, 1.0))",
+            ),
+            ("Player", "()"),
+        ];
+        let prefab = Prefab {
+            name: "Imp".to_string(),
+            components: components
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), ron::from_str(value).unwrap()))
+                .collect(),
+        };
+        game_state
+            .prefab_cache
+            .prefabs
+            .insert("imp".to_string(), prefab);
+
+        let overrides = PrefabOverrides::default()
+            .with(transform(2.0, 2.0))
+            .with(transform(3.0, 4.0));
+        let imp = game_state.spawn_prefab("imp", overrides).unwrap().id;
+        assert_eq!(game_state.get_entity(imp).unwrap().name(), "Imp");
+        assert_eq!(
+            game_state.get_component::<Transform>(imp).unwrap().position,
+            [3.0, 4.0]
+        );
+        assert!(game_state.get_component::<Player>(imp).is_some());
+    }
+
+    #[tokio::test]
+    async fn invalid_prefabs_spawn_nothing() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        let prefab = Prefab {
+            name: "Broken".to_string(),
+            components: [("Unregistered".to_string(), ron::from_str("()").unwrap())].into(),
+        };
+        game_state
+            .prefab_cache
+            .prefabs
+            .insert("broken".to_string(), prefab);
+
+        let result = game_state.spawn_prefab("broken", PrefabOverrides::default());
+        assert!(matches!(result, Err(PrefabError::Invalid(..))));
+        assert!(game_state.query::<EntityId, ()>().is_empty());
+    }
+}
@@ -0,0 +1,237 @@
+use crate::core::*;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// older spans are dropped once there are this many, which is a few seconds of frames
+pub const MAX_PROFILER_SPANS: usize = 100_000;
+// how many of the latest runs of a system its average is taken over
+pub const PROFILER_AVERAGE_WINDOW: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    Schedule,
+    Group,
+    System,
+}
+
+impl SpanKind {
+    fn category(self) -> &'static str {
+        match self {
+            SpanKind::Schedule => "schedule",
+            SpanKind::Group => "group",
+            SpanKind::System => "system",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub name: String,
+    pub kind: SpanKind,
+    // since the profiler was created
+    pub start: Duration,
+    pub duration: Duration,
+    pub thread: u64,
+}
+
+// Where and on which thread a span started. A system's future can be moved to another thread
+// while it is suspended, and the trace should show it where it started
+#[derive(Debug, Clone, Copy)]
+pub struct SpanStart {
+    at: Instant,
+    thread: u64,
+}
+
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // small stable numbers make for a readable trace, unlike `ThreadId`s
+    static THREAD: u64 = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records how long schedules, execution groups and systems take, while enabled.
+///
+/// The recorded spans can be exported with `chrome_trace` and opened in a trace viewer like
+/// `chrome://tracing` or Perfetto. Independent of that, the latest runs of every system are
+/// averaged so the game can show them at runtime.
+pub struct Profiler {
+    enabled: AtomicBool,
+    start: Instant,
+    spans: Mutex<VecDeque<Span>>,
+    // by id, as systems in different modules can have the same name
+    system_durations: Mutex<HashMap<SystemId, SystemDurations>>,
+}
+
+struct SystemDurations {
+    name: &'static str,
+    latest: VecDeque<Duration>,
+}
+
+impl SystemDurations {
+    fn average(&self) -> Duration {
+        self.latest.iter().sum::<Duration>() / self.latest.len() as u32
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler {
+            enabled: AtomicBool::new(false),
+            start: Instant::now(),
+            spans: Mutex::new(VecDeque::new()),
+            system_durations: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Profiler {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    // `None` while disabled, so timing costs nothing then
+    pub fn start(&self) -> Option<SpanStart> {
+        self.is_enabled().then(|| SpanStart {
+            at: Instant::now(),
+            thread: THREAD.with(|thread| *thread),
+        })
+    }
+
+    // the name is only formatted while enabled
+    pub fn record(&self, name: impl std::fmt::Display, kind: SpanKind, start: Option<SpanStart>) {
+        let Some(start) = start else {
+            return;
+        };
+        let duration = start.at.elapsed();
+
+        let mut spans = self.spans.lock().unwrap();
+        if spans.len() == MAX_PROFILER_SPANS {
+            spans.pop_front();
+        }
+        spans.push_back(Span {
+            name: name.to_string(),
+            kind,
+            start: start.at.duration_since(self.start),
+            duration,
+            thread: start.thread,
+        });
+    }
+
+    pub fn record_system(&self, id: SystemId, name: &'static str, start: Option<SpanStart>) {
+        let Some(start) = start else {
+            return;
+        };
+        let duration = start.at.elapsed();
+
+        let mut system_durations = self.system_durations.lock().unwrap();
+        let durations = system_durations
+            .entry(id)
+            .or_insert_with(|| SystemDurations {
+                name,
+                latest: VecDeque::new(),
+            });
+        if durations.latest.len() == PROFILER_AVERAGE_WINDOW {
+            durations.latest.pop_front();
+        }
+        durations.latest.push_back(duration);
+        drop(system_durations);
+
+        self.record(name, SpanKind::System, Some(start));
+    }
+
+    pub fn system_average(&self, id: SystemId) -> Option<Duration> {
+        let system_durations = self.system_durations.lock().unwrap();
+        Some(system_durations.get(&id)?.average())
+    }
+
+    // slowest first
+    pub fn system_averages(&self) -> Vec<(SystemId, &'static str, Duration)> {
+        let system_durations = self.system_durations.lock().unwrap();
+        let mut averages: Vec<_> = system_durations
+            .iter()
+            .map(|(id, durations)| (*id, durations.name, durations.average()))
+            .collect();
+        averages.sort_by_key(|(_, _, average)| std::cmp::Reverse(*average));
+        averages
+    }
+
+    pub fn spans(&self) -> Vec<Span> {
+        self.spans.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.spans.lock().unwrap().clear();
+        self.system_durations.lock().unwrap().clear();
+    }
+
+    /// The recorded spans in the Chrome trace event format, as complete ("X") events.
+    pub fn chrome_trace(&self) -> String {
+        let mut json = String::from("{\"traceEvents\":[");
+        for (i, span) in self.spans.lock().unwrap().iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write!(
+                json,
+                "\n{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":{}}}",
+                escape_json(&span.name),
+                span.kind.category(),
+                span.start.as_secs_f64() * 1e6,
+                span.duration.as_secs_f64() * 1e6,
+                span.thread
+            )
+            .unwrap();
+        }
+        json.push_str("\n],\"displayTimeUnit\":\"ms\"}\n");
+        json
+    }
+
+    pub fn save_chrome_trace(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.chrome_trace())
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn systems_with_the_same_name_are_averaged_apart() {
+        let profiler = Profiler::default();
+        profiler.set_enabled(true);
+        for id in [SystemId(0), SystemId(1)] {
+            profiler.record_system(id, "render", profiler.start());
+        }
+        profiler.set_enabled(false);
+        profiler.record_system(SystemId(2), "render", profiler.start());
+
+        let mut ids: Vec<_> = profiler
+            .system_averages()
+            .into_iter()
+            .map(|(id, name, _)| (id, name))
+            .collect();
+        ids.sort_by_key(|(id, _)| id.0);
+        assert_eq!(ids, [(SystemId(0), "render"), (SystemId(1), "render")]);
+        assert!(profiler.system_average(SystemId(2)).is_none());
+    }
+}
@@ -0,0 +1,456 @@
+use crate::core::*;
+use std::marker::PhantomData;
+
+// The component types a query touches, used to pick the storage to iterate and to catch
+// queries that would alias the same component mutably
+#[derive(Default, Debug)]
+pub struct QueryAccess {
+    pub required: Vec<ComponentType>,
+    pub reads: Vec<ComponentType>,
+    pub writes: Vec<ComponentType>,
+}
+
+impl QueryAccess {
+    fn read(&mut self, component_type: ComponentType) {
+        if self.writes.contains(&component_type) {
+            panic!("query accesses component type {component_type} both mutably and immutably");
+        }
+        self.reads.push(component_type);
+    }
+
+    fn write(&mut self, component_type: ComponentType) {
+        if self.writes.contains(&component_type) || self.reads.contains(&component_type) {
+            panic!("query accesses component type {component_type} mutably more than once");
+        }
+        self.writes.push(component_type);
+    }
+}
+
+/// What a `Query` yields for every matching entity, e.g. `(&Transform, &mut Velocity)`
+pub trait QueryData {
+    type Item<'q>;
+
+    fn access(access: &mut QueryAccess);
+
+    /// # Safety
+    /// No other live reference may alias a component this fetch hands out mutably
+    unsafe fn fetch<'q>(
+        game_state: &'q GameState,
+        context: &SystemContext,
+        entity: EntityId,
+    ) -> Option<Self::Item<'q>>;
+}
+
+/// Query data that never hands out mutable access, so it can be fetched through a shared
+/// `GameState` and held alongside other items of the same query
+///
+/// # Safety
+/// `fetch` must not hand out any mutable reference
+pub unsafe trait ReadOnlyQueryData: QueryData {}
+
+/// Narrows down which entities a `Query` matches without fetching anything
+pub trait QueryFilter {
+    fn access(_access: &mut QueryAccess) {}
+
+    fn matches(game_state: &GameState, context: &SystemContext, entity: EntityId) -> bool;
+}
+
+impl<T: Component> QueryData for &T {
+    type Item<'q> = &'q T;
+
+    fn access(access: &mut QueryAccess) {
+        access.required.push(T::get_component_type());
+        access.read(T::get_component_type());
+    }
+
+    unsafe fn fetch<'q>(
+        game_state: &'q GameState,
+        _context: &SystemContext,
+        entity: EntityId,
+    ) -> Option<Self::Item<'q>> {
+        game_state.get_component::<T>(entity)
+    }
+}
+
+unsafe impl<T: Component> ReadOnlyQueryData for &T {}
+
+impl<T: Component> QueryData for &mut T {
+    type Item<'q> = Mut<'q, T>;
+
+    fn access(access: &mut QueryAccess) {
+        access.required.push(T::get_component_type());
+        access.write(T::get_component_type());
+    }
+
+    unsafe fn fetch<'q>(
+        game_state: &'q GameState,
+        context: &SystemContext,
+        entity: EntityId,
+    ) -> Option<Self::Item<'q>> {
+        let (value, ticks) = game_state
+            .get_storage_mut::<T>()?
+            .get_with_ticks_mut(entity)?;
+        Some(Mut::new(value, ticks, context.this_run))
+    }
+}
+
+impl<T: Component> QueryData for Option<&T> {
+    type Item<'q> = Option<&'q T>;
+
+    fn access(access: &mut QueryAccess) {
+        access.read(T::get_component_type());
+    }
+
+    unsafe fn fetch<'q>(
+        game_state: &'q GameState,
+        context: &SystemContext,
+        entity: EntityId,
+    ) -> Option<Self::Item<'q>> {
+        Some(unsafe { <&T>::fetch(game_state, context, entity) })
+    }
+}
+
+unsafe impl<T: Component> ReadOnlyQueryData for Option<&T> {}
+
+impl<T: Component> QueryData for Option<&mut T> {
+    type Item<'q> = Option<Mut<'q, T>>;
+
+    fn access(access: &mut QueryAccess) {
+        access.write(T::get_component_type());
+    }
+
+    unsafe fn fetch<'q>(
+        game_state: &'q GameState,
+        context: &SystemContext,
+        entity: EntityId,
+    ) -> Option<Self::Item<'q>> {
+        Some(unsafe { <&mut T>::fetch(game_state, context, entity) })
+    }
+}
+
+impl QueryData for EntityId {
+    type Item<'q> = EntityId;
+
+    fn access(_access: &mut QueryAccess) {}
+
+    unsafe fn fetch<'q>(
+        game_state: &'q GameState,
+        _context: &SystemContext,
+        entity: EntityId,
+    ) -> Option<Self::Item<'q>> {
+        game_state.is_alive(entity).then_some(entity)
+    }
+}
+
+unsafe impl ReadOnlyQueryData for EntityId {}
+
+pub struct With<T: Component>(PhantomData<T>);
+pub struct Without<T: Component>(PhantomData<T>);
+
+impl<T: Component> QueryFilter for With<T> {
+    fn access(access: &mut QueryAccess) {
+        access.required.push(T::get_component_type());
+    }
+
+    fn matches(game_state: &GameState, _context: &SystemContext, entity: EntityId) -> bool {
+        game_state.has_component(entity, T::get_component_type())
+    }
+}
+
+impl<T: Component> QueryFilter for Without<T> {
+    fn matches(game_state: &GameState, _context: &SystemContext, entity: EntityId) -> bool {
+        game_state.is_alive(entity) && !game_state.has_component(entity, T::get_component_type())
+    }
+}
+
+impl QueryFilter for () {
+    fn matches(_game_state: &GameState, _context: &SystemContext, _entity: EntityId) -> bool {
+        true
+    }
+}
+
+macro_rules! impl_query_tuple {
+    ($($t:ident),+) => {
+        impl<$($t: QueryData),+> QueryData for ($($t,)+) {
+            type Item<'q> = ($($t::Item<'q>,)+);
+
+            fn access(access: &mut QueryAccess) {
+                $($t::access(access);)+
+            }
+
+            unsafe fn fetch<'q>(
+                game_state: &'q GameState,
+                context: &SystemContext,
+                entity: EntityId,
+            ) -> Option<Self::Item<'q>> {
+                Some(($(unsafe { $t::fetch(game_state, context, entity) }?,)+))
+            }
+        }
+
+        unsafe impl<$($t: ReadOnlyQueryData),+> ReadOnlyQueryData for ($($t,)+) {}
+
+        impl<$($t: QueryFilter),+> QueryFilter for ($($t,)+) {
+            fn access(access: &mut QueryAccess) {
+                $($t::access(access);)+
+            }
+
+            fn matches(game_state: &GameState, context: &SystemContext, entity: EntityId) -> bool {
+                $($t::matches(game_state, context, entity))&&+
+            }
+        }
+    };
+}
+
+impl_query_tuple!(A);
+impl_query_tuple!(A, B);
+impl_query_tuple!(A, B, C);
+impl_query_tuple!(A, B, C, D);
+impl_query_tuple!(A, B, C, D, E);
+impl_query_tuple!(A, B, C, D, E, F);
+impl_query_tuple!(A, B, C, D, E, F, G);
+impl_query_tuple!(A, B, C, D, E, F, G, H);
+
+/// Every entity matching `F`, along with the components `D` asks for.
+///
+/// Read-only queries come from `GameState::query` and can hand out any number of items at once.
+/// Queries with mutable access come from `GameState::query_mut`, which borrows the whole
+/// `GameState`, and their items borrow the query itself, so the same component can never be
+/// handed out twice:
+///
+/// ```compile_fail
+/// let mut query = game_state.query_mut::<&mut Transform, ()>();
+/// let first = query.get_mut(entity);
+/// let second = query.get_mut(entity); // error[E0499]
+/// ```
+pub struct Query<'a, D: QueryData, F: QueryFilter = ()> {
+    game_state: &'a GameState,
+    context: SystemContext,
+    access: QueryAccess,
+    _marker: PhantomData<(D, F)>,
+}
+
+impl<'a, D: ReadOnlyQueryData, F: QueryFilter> Query<'a, D, F> {
+    pub fn new(game_state: &'a GameState) -> Self {
+        unsafe { Query::new_unchecked(game_state) }
+    }
+
+    pub fn get(&self, entity: EntityId) -> Option<D::Item<'a>> {
+        unsafe { self.get_unchecked(entity) }
+    }
+
+    pub fn iter(&self) -> QueryIter<'a, D, F> {
+        unsafe { self.iter_unchecked() }
+    }
+
+    // returns `None` unless exactly one entity matches
+    pub fn single(&self) -> Option<D::Item<'a>> {
+        single(self.iter())
+    }
+}
+
+impl<'a, D: QueryData, F: QueryFilter> Query<'a, D, F> {
+    pub fn new_mut(game_state: &'a mut GameState) -> Self {
+        unsafe { Query::new_unchecked(game_state) }
+    }
+
+    /// # Safety
+    /// Nothing else may access the components `D` writes for as long as the query lives
+    unsafe fn new_unchecked(game_state: &'a GameState) -> Self {
+        let mut access = QueryAccess::default();
+        D::access(&mut access);
+        F::access(&mut access);
+
+        Query {
+            game_state,
+            context: SystemContext::current(game_state),
+            access,
+            _marker: PhantomData,
+        }
+    }
+
+    // iterates the smallest required storage rather than every entity
+    fn candidates(&self) -> Vec<EntityId> {
+        let smallest = self
+            .access
+            .required
+            .iter()
+            .map(|component_type| {
+                self.game_state
+                    .get_storage_by_type(*component_type)
+                    .map_or(&[][..], |storage| storage.entities())
+            })
+            .min_by_key(|entities| entities.len());
+
+        match smallest {
+            Some(entities) => entities.to_vec(),
+            None => self
+                .game_state
+                .entities
+                .iter()
+                .filter_map(|slot| slot.entity.as_ref())
+                .map(|entity| unsafe { &*entity.get() }.id)
+                .collect(),
+        }
+    }
+
+    /// # Safety
+    /// The item may not outlive any other item of this query that aliases it mutably
+    unsafe fn get_unchecked(&self, entity: EntityId) -> Option<D::Item<'a>> {
+        if !F::matches(self.game_state, &self.context, entity) {
+            return None;
+        }
+        unsafe { D::fetch(self.game_state, &self.context, entity) }
+    }
+
+    /// # Safety
+    /// Same as `get_unchecked`, for every item the iterator yields
+    unsafe fn iter_unchecked(&self) -> QueryIter<'a, D, F> {
+        QueryIter {
+            game_state: self.game_state,
+            context: self.context,
+            candidates: self.candidates().into_iter(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: EntityId) -> Option<D::Item<'_>> {
+        unsafe { self.get_unchecked(entity) }
+    }
+
+    pub fn iter_mut(&mut self) -> QueryIter<'_, D, F> {
+        unsafe { self.iter_unchecked() }
+    }
+
+    pub fn single_mut(&mut self) -> Option<D::Item<'_>> {
+        single(self.iter_mut())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        // the item is dropped straight away, and no `get_mut` item can be alive during `&self`
+        unsafe { self.iter_unchecked() }.next().is_none()
+    }
+}
+
+fn single<I: Iterator>(mut iter: I) -> Option<I::Item> {
+    let item = iter.next()?;
+    match iter.next() {
+        Some(_) => None,
+        None => Some(item),
+    }
+}
+
+// every entity is yielded once, so consuming the query can hand out items for its whole borrow
+impl<'a, D: QueryData, F: QueryFilter> IntoIterator for Query<'a, D, F> {
+    type Item = D::Item<'a>;
+    type IntoIter = QueryIter<'a, D, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        unsafe { self.iter_unchecked() }
+    }
+}
+
+impl<'q, D: QueryData, F: QueryFilter> IntoIterator for &'q mut Query<'_, D, F> {
+    type Item = D::Item<'q>;
+    type IntoIter = QueryIter<'q, D, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+pub struct QueryIter<'a, D: QueryData, F: QueryFilter> {
+    game_state: &'a GameState,
+    context: SystemContext,
+    candidates: std::vec::IntoIter<EntityId>,
+    _marker: PhantomData<(D, F)>,
+}
+
+impl<'a, D: QueryData, F: QueryFilter> Iterator for QueryIter<'a, D, F> {
+    type Item = D::Item<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for entity in self.candidates.by_ref() {
+            if !F::matches(self.game_state, &self.context, entity) {
+                continue;
+            }
+            if let Some(item) = unsafe { D::fetch(self.game_state, &self.context, entity) } {
+                return Some(item);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::marker::PhantomData;
+    use std::sync::Mutex;
+
+    // falls back to the trait's `false` when the inherent impl's bound does not hold
+    struct IsReadOnly<D>(PhantomData<D>);
+    trait NotReadOnly {
+        const READ_ONLY: bool = false;
+    }
+    impl<D> NotReadOnly for IsReadOnly<D> {}
+    impl<D: ReadOnlyQueryData> IsReadOnly<D> {
+        const READ_ONLY: bool = true;
+    }
+
+    // `GameState::query` only takes read-only data, so `query::<&mut T, ()>()` through a shared
+    // `GameState` is rejected at compile time
+    const _: () = {
+        assert!(IsReadOnly::<(EntityId, &Transform, Option<&Player>)>::READ_ONLY);
+        assert!(!IsReadOnly::<&mut Transform>::READ_ONLY);
+        assert!(!IsReadOnly::<(&Transform, Option<&mut Player>)>::READ_ONLY);
+    };
+
+    static SEEN_IN_FRAMES: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+
+    create_system!(write_once, get_write_once; writes Transform);
+    async fn write_once(game_state: &mut GameState, _t: f64, _dt: f64) {
+        if game_state.get_scheduler().frame_count() != 0 {
+            return;
+        }
+        for mut transform in game_state.query_mut::<&mut Transform, ()>() {
+            transform.rotation += 1.0;
+        }
+    }
+
+    create_system!(see_changes, get_see_changes; reads Transform);
+    async fn see_changes(game_state: &mut GameState, _t: f64, _dt: f64) {
+        let frame = game_state.get_scheduler().frame_count();
+        for _ in game_state.query::<&Transform, Changed<Transform>>() {
+            SEEN_IN_FRAMES.lock().unwrap().push(frame);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_write_is_seen_as_changed_exactly_once() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        scheduler
+            .add_system(get_write_once(), SystemType::Update)
+            .unwrap();
+        // runs before the write every frame, so it only sees it in the next one
+        scheduler
+            .add_system(get_see_changes().before("write_once"), SystemType::Update)
+            .unwrap();
+
+        let transform = Transform {
+            position: [0.0, 0.0],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+        };
+        let entity = game_state.create_entity("spinner".to_string());
+        entity.add_component(&mut game_state, transform);
+        // everything that exists before a system first runs counts as changed for it
+        scheduler.step(&mut game_state, 0.1).await;
+        SEEN_IN_FRAMES.lock().unwrap().clear();
+
+        for _ in 0..3 {
+            scheduler.step(&mut game_state, 0.1).await;
+        }
+        assert_eq!(*SEEN_IN_FRAMES.lock().unwrap(), [1]);
+    }
+}
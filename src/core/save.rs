@@ -0,0 +1,393 @@
+use crate::core::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashSet};
+
+// Components and resources are written under their type name without the module path, so a
+// save file survives types being moved around
+#[derive(Serialize, Deserialize, Default)]
+pub struct WorldSnapshot {
+    pub entities: Vec<EntitySnapshot>,
+    pub resources: BTreeMap<String, ron::Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    pub id: EntityId,
+    pub name: String,
+    pub components: BTreeMap<String, ron::Value>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Serialize(ron::Error),
+    Parse(ron::error::SpannedError),
+    Io(std::io::Error),
+    UnknownComponent(String),
+    DuplicateEntity(EntityId),
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SaveError::Serialize(e) => write!(f, "failed to (de)serialize world: {e}"),
+            SaveError::Parse(e) => write!(f, "failed to parse world: {e}"),
+            SaveError::Io(e) => write!(f, "failed to access save file: {e}"),
+            SaveError::UnknownComponent(name) => write!(
+                f,
+                "component `{name}` is not saveable, it has to be registered with `register_saveable` first"
+            ),
+            SaveError::DuplicateEntity(id) => write!(f, "entity {id} is saved more than once"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<ron::Error> for SaveError {
+    fn from(e: ron::Error) -> Self {
+        SaveError::Serialize(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for SaveError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        SaveError::Parse(e)
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+// Already deserialized, so inserting it can't fail anymore
+pub(crate) type StagedComponent = Box<dyn FnOnce(&mut GameState, EntityId) + Send>;
+type StagedResource = Box<dyn FnOnce(&mut GameState)>;
+
+struct SaveableComponent {
+    name: String,
+    component_type: ComponentType,
+    save: fn(&GameState, EntityId) -> Result<Option<ron::Value>, SaveError>,
+    load: fn(ron::Value) -> Result<StagedComponent, SaveError>,
+}
+
+struct SaveableResource {
+    name: String,
+    type_id: TypeId,
+    save: fn(&GameState) -> Result<Option<ron::Value>, SaveError>,
+    load: fn(ron::Value) -> Result<StagedResource, SaveError>,
+}
+
+// Only registered types are saved, everything else (like `GpuDevice`) is skipped
+#[derive(Default)]
+pub struct SaveRegistry {
+    components: Vec<SaveableComponent>,
+    resources: Vec<SaveableResource>,
+}
+
+fn to_value<T: Serialize>(value: &T) -> Result<ron::Value, SaveError> {
+    Ok(ron::from_str(&ron::to_string(value)?)?)
+}
+
+fn save_component<T: Component + Serialize>(
+    game_state: &GameState,
+    entity: EntityId,
+) -> Result<Option<ron::Value>, SaveError> {
+    game_state
+        .get_component::<T>(entity)
+        .map(to_value)
+        .transpose()
+}
+
+fn load_component<T: Component + DeserializeOwned>(
+    value: ron::Value,
+) -> Result<StagedComponent, SaveError> {
+    let component = value.into_rust::<T>()?;
+    Ok(Box::new(move |game_state, entity| {
+        if let Some(entity) = game_state.get_entity_mut(entity) {
+            entity.add_component(game_state, component);
+        }
+    }))
+}
+
+fn save_resource<T: Resource + Serialize>(
+    game_state: &GameState,
+) -> Result<Option<ron::Value>, SaveError> {
+    game_state
+        .get_resource::<T>()
+        .ok()
+        .map(to_value)
+        .transpose()
+}
+
+fn load_resource<T: Resource + DeserializeOwned>(
+    value: ron::Value,
+) -> Result<StagedResource, SaveError> {
+    let resource = value.into_rust::<T>()?;
+    Ok(Box::new(move |game_state| {
+        game_state.insert_resource(resource);
+    }))
+}
+
+impl GameState {
+    pub fn register_saveable<T: Component + Serialize + DeserializeOwned>(&mut self) {
+        let component_type = T::get_component_type();
+        let components = &mut self.save_registry.components;
+        if components
+            .iter()
+            .any(|saveable| saveable.component_type == component_type)
+        {
+            return;
+        }
+
+        let name = short_type_name(std::any::type_name::<T>());
+        if components.iter().any(|saveable| saveable.name == name) {
+            panic!("another saveable component is already called `{name}`");
+        }
+        components.push(SaveableComponent {
+            name,
+            component_type,
+            save: save_component::<T>,
+            load: load_component::<T>,
+        });
+    }
+
+    pub fn register_saveable_resource<T: Resource + Serialize + DeserializeOwned>(&mut self) {
+        let type_id = TypeId::of::<T>();
+        let resources = &mut self.save_registry.resources;
+        if resources.iter().any(|saveable| saveable.type_id == type_id) {
+            return;
+        }
+
+        let name = short_type_name(std::any::type_name::<T>());
+        if resources.iter().any(|saveable| saveable.name == name) {
+            panic!("another saveable resource is already called `{name}`");
+        }
+        resources.push(SaveableResource {
+            name,
+            type_id,
+            save: save_resource::<T>,
+            load: load_resource::<T>,
+        });
+    }
+
+    // adds the saveable component registered under `name`, replacing any the entity already has
+    pub fn load_component_by_name(
+        &mut self,
+        entity: EntityId,
+        name: String,
+        value: ron::Value,
+    ) -> Result<(), SaveError> {
+        self.stage_component(name, value)?(self, entity);
+        Ok(())
+    }
+
+    pub(crate) fn stage_component(
+        &self,
+        name: String,
+        value: ron::Value,
+    ) -> Result<StagedComponent, SaveError> {
+        let load = self
+            .save_registry
+            .components
+            .iter()
+            .find(|saveable| saveable.name == name)
+            .map(|saveable| saveable.load)
+            .ok_or(SaveError::UnknownComponent(name))?;
+        load(value)
+    }
+
+    pub fn snapshot_world(&self) -> Result<WorldSnapshot, SaveError> {
+        let mut snapshot = WorldSnapshot::default();
+
+        for slot in &self.entities {
+            let Some(entity) = &slot.entity else {
+                continue;
+            };
+            let entity = unsafe { &*entity.get() };
+
+            let mut components = BTreeMap::new();
+            for saveable in &self.save_registry.components {
+                if !entity.has_component(saveable.component_type) {
+                    continue;
+                }
+                if let Some(value) = (saveable.save)(self, entity.id)? {
+                    components.insert(saveable.name.clone(), value);
+                }
+            }
+
+            snapshot.entities.push(EntitySnapshot {
+                id: entity.id,
+                name: entity.name().to_string(),
+                components,
+            });
+        }
+
+        for saveable in &self.save_registry.resources {
+            if let Some(value) = (saveable.save)(self)? {
+                snapshot.resources.insert(saveable.name.clone(), value);
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    // despawns every entity and replaces them with the snapshot's, keeping their ids. Saveable
+    // resources are only replaced if the snapshot contains them, all others are left alone.
+    // Nothing is despawned unless the whole snapshot can be loaded
+    pub fn restore_world(&mut self, snapshot: WorldSnapshot) -> Result<(), SaveError> {
+        let mut indices = HashSet::new();
+        let mut entities = Vec::with_capacity(snapshot.entities.len());
+        for entity in snapshot.entities {
+            if !indices.insert(entity.id.index) {
+                return Err(SaveError::DuplicateEntity(entity.id));
+            }
+            let components = entity
+                .components
+                .into_iter()
+                .map(|(name, value)| self.stage_component(name, value))
+                .collect::<Result<Vec<_>, _>>()?;
+            entities.push((entity.id, entity.name, components));
+        }
+
+        let mut resources = Vec::new();
+        for (name, value) in snapshot.resources {
+            let load = self
+                .save_registry
+                .resources
+                .iter()
+                .find(|saveable| saveable.name == name)
+                .map(|saveable| saveable.load);
+            // a resource that is no longer saveable is not worth failing the whole load over
+            if let Some(load) = load {
+                resources.push(load(value)?);
+            }
+        }
+
+        let alive: Vec<EntityId> = self
+            .entities
+            .iter()
+            .filter_map(|slot| slot.entity.as_ref())
+            .map(|entity| unsafe { &*entity.get() }.id)
+            .collect();
+        for entity in alive {
+            self.despawn_entity(entity);
+        }
+
+        for (id, name, components) in entities {
+            // the indices are unique and every slot is free now
+            self.create_entity_with_id(id, name).unwrap();
+            for component in components {
+                component(self, id);
+            }
+        }
+
+        for resource in resources {
+            resource(self);
+        }
+
+        Ok(())
+    }
+
+    pub fn save_world(&self) -> Result<String, SaveError> {
+        let snapshot = self.snapshot_world()?;
+        Ok(ron::ser::to_string_pretty(
+            &snapshot,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    pub fn load_world(&mut self, world: &str) -> Result<(), SaveError> {
+        self.restore_world(ron::from_str(world)?)
+    }
+
+    pub fn save_world_to_file(&self, path: impl AsRef<std::path::Path>) -> Result<(), SaveError> {
+        std::fs::write(path, self.save_world()?)?;
+        Ok(())
+    }
+
+    pub fn load_world_from_file(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), SaveError> {
+        self.load_world(&std::fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde::{Deserialize, Serialize};
+
+    mod first {
+        use super::*;
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Score(pub u32);
+        impl_resource!(Score);
+    }
+
+    mod second {
+        use super::*;
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Score(pub u32);
+        impl_resource!(Score);
+    }
+
+    #[test]
+    #[should_panic(expected = "another saveable resource is already called `Score`")]
+    fn resources_with_the_same_name_collide() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        game_state.register_saveable_resource::<first::Score>();
+        // registering the same type again is fine
+        game_state.register_saveable_resource::<first::Score>();
+        game_state.register_saveable_resource::<second::Score>();
+    }
+
+    #[tokio::test]
+    async fn broken_snapshots_leave_the_world_alone() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        game_state.register_saveable::<Transform>();
+        let transform = Transform {
+            position: [1.0, 2.0],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+        };
+        let entity = game_state.create_entity("kept".to_string());
+        entity.add_component(&mut game_state, transform);
+        let saved = game_state.save_world().unwrap();
+
+        let unknown = saved.replace("Transform", "Transfrom");
+        let malformed = saved.replace("rotation", "rotaton");
+        let duplicated = {
+            let mut snapshot: WorldSnapshot = ron::from_str(&saved).unwrap();
+            let copy = EntitySnapshot {
+                id: snapshot.entities[0].id,
+                name: "copy".to_string(),
+                components: Default::default(),
+            };
+            snapshot.entities.push(copy);
+            ron::to_string(&snapshot).unwrap()
+        };
+
+        assert!(matches!(
+            game_state.load_world(&unknown),
+            Err(SaveError::UnknownComponent(_))
+        ));
+        assert!(matches!(
+            game_state.load_world(&malformed),
+            Err(SaveError::Serialize(_))
+        ));
+        assert!(matches!(
+            game_state.load_world(&duplicated),
+            Err(SaveError::DuplicateEntity(_))
+        ));
+        assert_eq!(game_state.save_world().unwrap(), saved);
+    }
+}
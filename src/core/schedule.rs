@@ -0,0 +1,226 @@
+use crate::core::*;
+
+/// Refers to one or more systems in `before`/`after` constraints: a system's function name,
+/// a label given with `System::label`, or a `SystemSet`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SystemLabel(pub &'static str);
+
+impl From<&'static str> for SystemLabel {
+    fn from(label: &'static str) -> Self {
+        SystemLabel(label)
+    }
+}
+
+impl From<SystemSet> for SystemLabel {
+    fn from(set: SystemSet) -> Self {
+        SystemLabel(set.name())
+    }
+}
+
+/// Broad stages of a schedule. Every system in a set runs after all systems in the sets before
+/// it, systems outside of any set are only ordered by their own constraints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SystemSet {
+    Input,
+    Simulation,
+    Render,
+}
+
+impl SystemSet {
+    pub fn name(self) -> &'static str {
+        match self {
+            SystemSet::Input => "Input",
+            SystemSet::Simulation => "Simulation",
+            SystemSet::Render => "Render",
+        }
+    }
+}
+
+pub type RunCondition = Box<dyn Fn(&GameState) -> bool + Send + Sync>;
+
+impl System {
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.labels.push(SystemLabel(label));
+        self
+    }
+
+    pub fn before(mut self, label: impl Into<SystemLabel>) -> Self {
+        self.before.push(label.into());
+        self
+    }
+
+    pub fn after(mut self, label: impl Into<SystemLabel>) -> Self {
+        self.after.push(label.into());
+        self
+    }
+
+    pub fn in_set(mut self, set: SystemSet) -> Self {
+        self.set = Some(set);
+        self
+    }
+
+    // the system is skipped unless all of its conditions hold when its group starts
+    pub fn run_if(
+        mut self,
+        condition: impl Fn(&GameState) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.conditions.push(Box::new(condition));
+        self
+    }
+
+    pub fn should_run(&self, game_state: &GameState) -> bool {
+        !self.paused.load(std::sync::atomic::Ordering::Relaxed)
+            && self
+                .conditions
+                .iter()
+                .all(|condition| condition(game_state))
+    }
+
+    pub fn has_label(&self, label: SystemLabel) -> bool {
+        self.name == label.0
+            || self.labels.contains(&label)
+            || self.set.is_some_and(|set| set.name() == label.0)
+    }
+}
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    // the systems making up the cycle, starting and ending with the same one
+    Cycle(Vec<&'static str>),
+}
+
+impl std::fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ScheduleError::Cycle(systems) => write!(
+                f,
+                "systems can't be ordered because of the cycle `{}`",
+                systems.join("` -> `")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+#[derive(Default)]
+pub struct Schedule {
+    pub systems: Vec<System>,
+    pub execution_order: Vec<Vec<usize>>,
+}
+
+impl Schedule {
+    // the indices of the systems that have to run before each system
+    fn dependencies(&self) -> Vec<Vec<usize>> {
+        let systems = &self.systems;
+        let mut dependencies = vec![Vec::new(); systems.len()];
+
+        let mut add = |before: usize, after: usize| {
+            if before != after && !dependencies[after].contains(&before) {
+                dependencies[after].push(before);
+            }
+        };
+
+        for (i, system) in systems.iter().enumerate() {
+            for (j, other) in systems.iter().enumerate() {
+                if system.before.iter().any(|label| other.has_label(*label)) {
+                    add(i, j);
+                }
+                if system.after.iter().any(|label| other.has_label(*label)) {
+                    add(j, i);
+                }
+                if let (Some(set), Some(other_set)) = (system.set, other.set) {
+                    if set < other_set {
+                        add(i, j);
+                    }
+                }
+            }
+        }
+
+        dependencies
+    }
+
+    // splits the systems into levels that only depend on earlier levels, then groups the systems
+    // of every level by conflicting access
+    pub fn generate_execution_order(&mut self) -> Result<(), ScheduleError> {
+        let dependencies = self.dependencies();
+        let mut done = vec![false; self.systems.len()];
+        let mut execution_order = Vec::new();
+
+        loop {
+            let level: Vec<usize> = (0..self.systems.len())
+                .filter(|i| !done[*i] && dependencies[*i].iter().all(|before| done[*before]))
+                .collect();
+            if level.is_empty() {
+                break;
+            }
+            for i in &level {
+                done[*i] = true;
+            }
+            execution_order.extend(self.group_by_conflicts(&level));
+        }
+
+        if let Some(start) = done.iter().position(|done| !done) {
+            return Err(ScheduleError::Cycle(self.find_cycle(
+                start,
+                &dependencies,
+                &done,
+            )));
+        }
+
+        self.execution_order = execution_order;
+        Ok(())
+    }
+
+    // greedily puts every system into the first group it doesn't conflict with, keeping the
+    // order systems were added in within each group
+    fn group_by_conflicts(&self, level: &[usize]) -> Vec<Vec<usize>> {
+        let mut groups: Vec<(Vec<usize>, SystemAccess)> = Vec::new();
+
+        for i in level {
+            let access = &self.systems[*i].access;
+            match groups
+                .iter_mut()
+                .find(|(_, group_access)| !group_access.conflicts_with(access))
+            {
+                Some((group, group_access)) => {
+                    group.push(*i);
+                    group_access.extend(access);
+                }
+                None => groups.push((vec![*i], access.clone())),
+            }
+        }
+
+        groups.into_iter().map(|(group, _)| group).collect()
+    }
+
+    // every system left over depends on another one left over, so walking those dependencies
+    // has to end up going in a circle
+    fn find_cycle(
+        &self,
+        start: usize,
+        dependencies: &[Vec<usize>],
+        done: &[bool],
+    ) -> Vec<&'static str> {
+        let mut path = vec![start];
+        loop {
+            let current = *path.last().unwrap();
+            let next = *dependencies[current]
+                .iter()
+                .find(|before| !done[**before])
+                .unwrap();
+
+            if let Some(position) = path.iter().position(|system| *system == next) {
+                // dependencies point backwards, so the path is reversed to read in running order
+                let mut cycle: Vec<&'static str> = path[position..]
+                    .iter()
+                    .rev()
+                    .map(|system| self.systems[*system].name)
+                    .collect();
+                cycle.push(cycle[0]);
+                return cycle;
+            }
+            path.push(next);
+        }
+    }
+}
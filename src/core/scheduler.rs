@@ -1,26 +1,84 @@
 use crate::core::*;
-use futures::future::poll_fn;
 use std::cell::SyncUnsafeCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::time::Duration;
 use tokio::time::Instant;
 
-pub struct Scheduler {
-    init_systems: Vec<System>,
-    update_systems: Vec<System>,
-    fixed_update_systems: Vec<System>,
-    close_systems: Vec<System>,
+// how many fixed updates a single update may run to catch up, anything further behind is dropped
+// so a slow frame can't make every following frame slower
+pub const MAX_FIXED_STEPS_PER_UPDATE: u32 = 5;
+// how long the Close schedule gets to flush saves and disconnect before the game exits anyway
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// what `get_schedule` returns for states nothing was added to
+static EMPTY_SCHEDULE: Schedule = Schedule {
+    systems: Vec::new(),
+    execution_order: Vec::new(),
+};
 
-    init_execution_order: Vec<Vec<usize>>,
-    update_execution_order: Vec<Vec<usize>>,
-    fixed_update_execution_order: Vec<Vec<usize>>,
-    close_execution_order: Vec<Vec<usize>>,
+pub struct Scheduler {
+    init: Schedule,
+    update: Schedule,
+    fixed_update: Schedule,
+    close: Schedule,
+    // `OnEnter` and `OnExit` schedules, created when the first system is added to them
+    state_schedules: HashMap<SystemType, Schedule>,
+    // shared with the tasks systems run in
+    profiler: Arc<Profiler>,
+    // applied at the start of the next init or update, when no system is running
+    system_changes: std::sync::Mutex<Vec<SystemChange>>,
+    // the changes that couldn't be applied, until `take_system_change_errors` is called
+    system_change_errors: std::sync::Mutex<Vec<SystemChangeError>>,
+    next_system_id: u64,
 
     execution_lock: SchedulerLock,
 
     fixed_update_interval: Duration,
-    start_time: Instant,
+    clock: Clock,
     prev_time: SyncUnsafeCell<f64>,
+    // updates finished so far, shared with `Time`
+    frame_count: Arc<AtomicU64>,
+    // time not yet simulated by fixed updates, always less than `fixed_update_interval` between
+    // updates
+    fixed_accumulator: SyncUnsafeCell<f64>,
+    // time simulated by fixed updates, what they get as `t`
+    fixed_elapsed: SyncUnsafeCell<f64>,
+    closed: AtomicBool,
+}
+
+enum SystemChange {
+    Remove(SystemId),
+    Replace(SystemId, System),
+}
+
+#[derive(Debug)]
+pub enum SystemChangeError {
+    // removed before the change was applied, or never added
+    NotFound(SystemId),
+    // the old system was kept
+    Cycle(SystemId, ScheduleError),
+}
+
+impl std::fmt::Display for SystemChangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SystemChangeError::NotFound(id) => write!(f, "system {id:?} doesn't exist"),
+            SystemChangeError::Cycle(id, e) => write!(f, "couldn't replace system {id:?}: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SystemChangeError {}
+
+// Where the time passed to systems comes from
+enum Clock {
+    Real(Instant),
+    // seconds, only advanced by `Scheduler::step`
+    Manual(SyncUnsafeCell<f64>),
 }
 
 struct SchedulerLock(Mutex<bool>);
@@ -47,6 +105,7 @@ impl SchedulerLock {
 }
 
 impl Scheduler {
+    // drives fixed updates on their own, for when nothing calls `update`, e.g. a headless server
     pub async fn loop_fixed_update(&self, game_state: *mut GameState) {
         let mut time = Instant::now();
 
@@ -56,7 +115,7 @@ impl Scheduler {
             let dur = Instant::now().duration_since(time);
 
             if dur < self.fixed_update_interval {
-                tokio::time::sleep(dur).await;
+                tokio::time::sleep(self.fixed_update_interval - dur).await;
             } else {
                 eprintln!(
                     "Fixed update overran by {:?}",
@@ -69,62 +128,215 @@ impl Scheduler {
     }
 
     pub fn new(fixed_update_interval: f64) -> Scheduler {
-        Scheduler {
-            init_systems: Vec::new(),
-            update_systems: Vec::new(),
-            fixed_update_systems: Vec::new(),
-            close_systems: Vec::new(),
+        Self::with_clock(fixed_update_interval, Clock::Real(Instant::now()))
+    }
 
-            init_execution_order: Vec::new(),
-            update_execution_order: Vec::new(),
-            fixed_update_execution_order: Vec::new(),
-            close_execution_order: Vec::new(),
+    /// A scheduler whose time only moves when `step` is called, for headless simulations,
+    /// replays and tests that need the same results every run.
+    ///
+    /// The systems of a group run one after another in the order they were added instead of on
+    /// separate threads, so commands they queue are always applied in the same order.
+    pub fn with_manual_clock(fixed_update_interval: f64) -> Scheduler {
+        Self::with_clock(
+            fixed_update_interval,
+            Clock::Manual(SyncUnsafeCell::new(0.0)),
+        )
+    }
+
+    fn with_clock(fixed_update_interval: f64, clock: Clock) -> Scheduler {
+        Scheduler {
+            init: Schedule::default(),
+            update: Schedule::default(),
+            fixed_update: Schedule::default(),
+            close: Schedule::default(),
+            state_schedules: HashMap::new(),
+            profiler: Arc::new(Profiler::default()),
+            system_changes: std::sync::Mutex::new(Vec::new()),
+            system_change_errors: std::sync::Mutex::new(Vec::new()),
+            next_system_id: 0,
 
             execution_lock: SchedulerLock(Mutex::new(false)),
 
             fixed_update_interval: Duration::from_secs_f64(fixed_update_interval),
-            start_time: Instant::now(),
+            clock,
             prev_time: SyncUnsafeCell::new(0.0),
+            frame_count: Arc::new(AtomicU64::new(0)),
+            fixed_accumulator: SyncUnsafeCell::new(0.0),
+            fixed_elapsed: SyncUnsafeCell::new(0.0),
+            closed: AtomicBool::new(false),
         }
     }
 
-    pub fn add_system(&mut self, system: System, system_type: SystemType) {
+    pub fn get_schedule(&self, system_type: SystemType) -> &Schedule {
         match system_type {
-            SystemType::Init => {
-                self.init_systems.push(system);
-                self.init_execution_order =
-                    self.generate_execution_order_for_systems(&self.init_systems);
-            }
-            SystemType::Update => {
-                self.update_systems.push(system);
-                self.update_execution_order =
-                    self.generate_execution_order_for_systems(&self.update_systems);
-            }
-            SystemType::FixedUpdate => {
-                self.fixed_update_systems.push(system);
-                self.fixed_update_execution_order =
-                    self.generate_execution_order_for_systems(&self.fixed_update_systems);
-            }
-            SystemType::Close => {
-                self.close_systems.push(system);
-                self.close_execution_order =
-                    self.generate_execution_order_for_systems(&self.close_systems);
+            SystemType::Init => &self.init,
+            SystemType::Update => &self.update,
+            SystemType::FixedUpdate => &self.fixed_update,
+            SystemType::Close => &self.close,
+            SystemType::OnEnter(_) | SystemType::OnExit(_) => self
+                .state_schedules
+                .get(&system_type)
+                .unwrap_or(&EMPTY_SCHEDULE),
+        }
+    }
+
+    pub fn get_schedule_mut(&mut self, system_type: SystemType) -> &mut Schedule {
+        match system_type {
+            SystemType::Init => &mut self.init,
+            SystemType::Update => &mut self.update,
+            SystemType::FixedUpdate => &mut self.fixed_update,
+            SystemType::Close => &mut self.close,
+            SystemType::OnEnter(_) | SystemType::OnExit(_) => {
+                self.state_schedules.entry(system_type).or_default()
             }
-        };
+        }
+    }
+
+    // the system is not added if its ordering constraints would form a cycle
+    pub fn add_system(
+        &mut self,
+        system: System,
+        system_type: SystemType,
+    ) -> Result<SystemId, ScheduleError> {
+        let id = self.add_system_without_execution_order_generation(system, system_type);
+        let schedule = self.get_schedule_mut(system_type);
+        if let Err(e) = schedule.generate_execution_order() {
+            schedule.systems.pop();
+            return Err(e);
+        }
+        Ok(id)
     }
 
     // you need to ensure that you call `generate_execution_order` for the system to be run
     pub fn add_system_without_execution_order_generation(
         &mut self,
-        system: System,
+        mut system: System,
         system_type: SystemType,
-    ) {
-        match system_type {
-            SystemType::Init => self.init_systems.push(system),
-            SystemType::Update => self.update_systems.push(system),
-            SystemType::FixedUpdate => self.fixed_update_systems.push(system),
-            SystemType::Close => self.close_systems.push(system),
-        };
+    ) -> SystemId {
+        let id = SystemId(self.next_system_id);
+        self.next_system_id += 1;
+        system.id = id;
+        self.get_schedule_mut(system_type).systems.push(system);
+        id
+    }
+
+    fn schedules(&self) -> impl Iterator<Item = (SystemType, &Schedule)> {
+        [
+            (SystemType::Init, &self.init),
+            (SystemType::Update, &self.update),
+            (SystemType::FixedUpdate, &self.fixed_update),
+            (SystemType::Close, &self.close),
+        ]
+        .into_iter()
+        .chain(
+            self.state_schedules
+                .iter()
+                .map(|(system_type, schedule)| (*system_type, schedule)),
+        )
+    }
+
+    // the schedule the system is in and its index there
+    fn find_system(&self, id: SystemId) -> Option<(SystemType, usize)> {
+        self.schedules().find_map(|(system_type, schedule)| {
+            let index = schedule.systems.iter().position(|system| system.id == id)?;
+            Some((system_type, index))
+        })
+    }
+
+    pub fn get_system(&self, id: SystemId) -> Option<&System> {
+        let (system_type, index) = self.find_system(id)?;
+        Some(&self.get_schedule(system_type).systems[index])
+    }
+
+    // takes effect from the next execution group on, returns false if there is no such system
+    pub fn pause_system(&self, id: SystemId) -> bool {
+        self.set_system_paused(id, true)
+    }
+
+    pub fn resume_system(&self, id: SystemId) -> bool {
+        self.set_system_paused(id, false)
+    }
+
+    fn set_system_paused(&self, id: SystemId, paused: bool) -> bool {
+        match self.get_system(id) {
+            Some(system) => {
+                system.paused.store(paused, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the system at the start of the next update, so it can be called from systems.
+    pub fn remove_system(&self, id: SystemId) {
+        self.system_changes
+            .lock()
+            .unwrap()
+            .push(SystemChange::Remove(id));
+    }
+
+    /// Swaps the system for `system` at the start of the next update, so it can be called from
+    /// systems. The new system keeps the id, schedule and paused state of the old one.
+    ///
+    /// The old system is kept if the ordering constraints of the new one would form a cycle,
+    /// which `take_system_change_errors` reports.
+    pub fn replace_system(&self, id: SystemId, system: System) {
+        self.system_changes
+            .lock()
+            .unwrap()
+            .push(SystemChange::Replace(id, system));
+    }
+
+    // removals and replacements that failed since the last call, oldest first
+    pub fn take_system_change_errors(&self) -> Vec<SystemChangeError> {
+        std::mem::take(&mut *self.system_change_errors.lock().unwrap())
+    }
+
+    // only the schedules that changed get a new execution order
+    fn apply_system_changes(&mut self, game_state: &mut GameState) {
+        let changes = std::mem::take(&mut *self.system_changes.lock().unwrap());
+        let mut errors = Vec::new();
+        for change in changes {
+            let id = match &change {
+                SystemChange::Remove(id) | SystemChange::Replace(id, _) => *id,
+            };
+            let Some((system_type, index)) = self.find_system(id) else {
+                errors.push(SystemChangeError::NotFound(id));
+                continue;
+            };
+            let schedule = self.get_schedule_mut(system_type);
+
+            match change {
+                SystemChange::Remove(_) => {
+                    schedule.systems.remove(index);
+                    // fewer constraints can't form a cycle
+                    schedule.generate_execution_order().unwrap();
+                    game_state.remove_event_readers(id);
+                }
+                SystemChange::Replace(_, mut system) => {
+                    system.id = id;
+                    let old = &schedule.systems[index];
+                    system
+                        .paused
+                        .store(old.paused.load(Ordering::Relaxed), Ordering::Relaxed);
+                    let old = std::mem::replace(&mut schedule.systems[index], system);
+
+                    match schedule.generate_execution_order() {
+                        Ok(()) => game_state.remove_event_readers(id),
+                        Err(e) => {
+                            // the execution order is only replaced when there is no cycle
+                            schedule.systems[index] = old;
+                            errors.push(SystemChangeError::Cycle(id, e));
+                        }
+                    }
+                }
+            }
+        }
+
+        for e in &errors {
+            eprintln!("{e}");
+        }
+        self.system_change_errors.lock().unwrap().extend(errors);
     }
 
     pub async fn init(&mut self, game_state: &mut GameState) {
@@ -135,13 +347,15 @@ impl Scheduler {
         }
 
         self.execution_lock.lock().await;
-        for group in self.init_execution_order.iter() {
-            Self::await_group(group, &self.init_systems, game_state, time, dt).await;
-        }
+        self.apply_system_changes(game_state);
+        let (time, dt) = Self::virtual_time(game_state, time, dt);
+        self.run_schedule(SystemType::Init, game_state, time, dt)
+            .await;
+        self.run_state_transitions(game_state, time, dt).await;
         self.execution_lock.unlock().await;
     }
 
-    pub async fn update(&self, game_state: &mut GameState) {
+    pub async fn update(&mut self, game_state: &mut GameState) {
         let time = self.get_time();
         // this is ok because update and init are never run at the same time
         let dt = time - unsafe { *self.prev_time.get() };
@@ -149,27 +363,126 @@ impl Scheduler {
             self.prev_time.get().write(time);
         }
 
+        self.run_update(game_state, time, dt).await;
+    }
+
+    /// Advances the manual clock by exactly `dt` seconds and runs an update with that `dt`,
+    /// including the fixed updates that fit into it.
+    ///
+    /// # Panics
+    /// If the scheduler wasn't created with `with_manual_clock`
+    pub async fn step(&mut self, game_state: &mut GameState, dt: f64) {
+        let Clock::Manual(manual_time) = &self.clock else {
+            panic!("`step` needs a scheduler created with `Scheduler::with_manual_clock`");
+        };
+        // this is ok because update and init are never run at the same time
+        let time = unsafe {
+            let manual_time = &mut *manual_time.get();
+            *manual_time += dt;
+            *manual_time
+        };
+        unsafe {
+            self.prev_time.get().write(time);
+        }
+
+        self.run_update(game_state, time, dt).await;
+    }
+
+    async fn run_update(&mut self, game_state: &mut GameState, time: f64, dt: f64) {
         // used to ensure that update and fixed_update don't run at the same time
         self.execution_lock.lock().await;
-        for group in self.update_execution_order.iter() {
-            Self::await_group(group, &self.update_systems, game_state, time, dt).await;
+        self.apply_system_changes(game_state);
+        // without a `Time` resource the virtual clock is the real one
+        let virtual_dt = match game_state.get_resource_mut::<Time>() {
+            Ok(time_resource) => time_resource.advance(time, dt),
+            Err(_) => dt,
+        };
+        let fixed_steps = self.run_fixed_steps(game_state, virtual_dt).await;
+        let (time, dt) = Self::virtual_time(game_state, time, dt);
+        self.run_schedule(SystemType::Update, game_state, time, dt)
+            .await;
+        // events sent this frame stay readable through the next one, and through the next fixed
+        // update for fixed readers
+        if fixed_steps > 0 || self.fixed_update.systems.is_empty() {
+            game_state.update_events();
         }
+        self.run_state_transitions(game_state, time, dt).await;
+        self.frame_count.fetch_add(1, Ordering::Relaxed);
         self.execution_lock.unlock().await;
     }
 
     pub async fn fixed_update(&self, game_state: *mut GameState) {
-        let time = self.get_time();
-        let dt = self.fixed_update_interval.as_secs_f64();
-
         // used to ensure that update and fixed_update don't run at the same time
         self.execution_lock.lock().await;
-        for group in self.fixed_update_execution_order.iter() {
-            Self::await_group(group, &self.fixed_update_systems, game_state, time, dt).await;
-        }
+        self.run_fixed_update_groups(game_state).await;
         self.execution_lock.unlock().await;
     }
 
+    // every fixed update moves time on by exactly one interval, however late it runs
+    async fn run_fixed_update_groups(&self, game_state: *mut GameState) {
+        let dt = self.fixed_update_interval.as_secs_f64();
+        // this is ok because the execution lock is held
+        let time = unsafe {
+            let fixed_elapsed = &mut *self.fixed_elapsed.get();
+            *fixed_elapsed += dt;
+            *fixed_elapsed
+        };
+        if let Ok(fixed_time) = unsafe { &mut *game_state }.get_resource_mut::<FixedTime>() {
+            fixed_time.advance();
+        }
+        self.run_schedule(SystemType::FixedUpdate, game_state, time, dt)
+            .await;
+    }
+
+    // runs as many fixed updates as fit into the virtual time passed since the last update,
+    // returning how many ran
+    async fn run_fixed_steps(&self, game_state: *mut GameState, virtual_dt: f64) -> u32 {
+        let interval = self.fixed_update_interval.as_secs_f64();
+        // this is ok because the execution lock is held
+        let accumulator = unsafe { &mut *self.fixed_accumulator.get() };
+        *accumulator += virtual_dt;
+
+        let mut steps = 0;
+        while *accumulator >= interval && steps < MAX_FIXED_STEPS_PER_UPDATE {
+            self.run_fixed_update_groups(game_state).await;
+            *accumulator -= interval;
+            steps += 1;
+        }
+
+        if *accumulator >= interval {
+            eprintln!(
+                "Fixed update fell behind by {:?}, skipping",
+                Duration::from_secs_f64(*accumulator - *accumulator % interval)
+            );
+            *accumulator %= interval;
+        }
+
+        let alpha = *accumulator / interval;
+        if let Ok(fixed_time) = unsafe { &mut *game_state }.get_resource_mut::<FixedTime>() {
+            fixed_time.set_alpha(alpha);
+        }
+        steps
+    }
+
+    /// How far the current update is between the last fixed update and the next one, from 0 to 1.
+    ///
+    /// Rendering can use it to blend between the last two fixed states, so movement simulated in
+    /// fixed updates looks smooth at any frame rate.
+    pub fn fixed_update_alpha(&self) -> f64 {
+        let accumulator = unsafe { *self.fixed_accumulator.get() };
+        accumulator / self.fixed_update_interval.as_secs_f64()
+    }
+
+    pub fn fixed_update_interval(&self) -> f64 {
+        self.fixed_update_interval.as_secs_f64()
+    }
+
+    // only the first call runs the Close schedule, however the game ends up being closed
     pub async fn close(&self, game_state: &mut GameState) {
+        if self.closed.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
         let time = self.get_time();
         let dt = time - unsafe { *self.prev_time.get() };
         unsafe {
@@ -177,99 +490,317 @@ impl Scheduler {
         }
 
         self.execution_lock.lock().await;
-        for group in self.close_execution_order.iter() {
-            Self::await_group(group, &self.close_systems, game_state, time, dt).await;
-        }
+        let (time, dt) = Self::virtual_time(game_state, time, dt);
+        self.run_schedule(SystemType::Close, game_state, time, dt)
+            .await;
         self.execution_lock.unlock().await;
     }
 
+    /// Runs `close`, but gives up after `timeout`, including time spent waiting for a running
+    /// update. Returns whether the Close schedule finished.
+    ///
+    /// Systems still running when it gives up keep running and may still use the game state,
+    /// so the process should exit right away in that case.
+    pub async fn close_with_timeout(&self, game_state: &mut GameState, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, self.close(game_state))
+            .await
+            .is_ok()
+    }
+
+    /// # Safety
+    /// Must only be called while no other update or fixed update is running, otherwise both
+    /// will be allowed to mutate the `GameState` at the same time
     pub async unsafe fn force_unlock(&self) {
         self.execution_lock.unlock().await;
     }
 
+    /// # Safety
+    /// The lock must later be released with `force_unlock`, otherwise every following update
+    /// will wait forever
     pub async unsafe fn force_lock(&self) {
         self.execution_lock.lock().await;
     }
 
+    async fn run_state_transitions(&self, game_state: *mut GameState, time: f64, dt: f64) {
+        let transitions = unsafe { &mut *game_state }.apply_state_transitions();
+        for transition in transitions {
+            if let Some(exited) = transition.exited {
+                self.run_schedule(SystemType::OnExit(exited), game_state, time, dt)
+                    .await;
+            }
+            self.run_schedule(
+                SystemType::OnEnter(transition.entered),
+                game_state,
+                time,
+                dt,
+            )
+            .await;
+        }
+    }
+
+    async fn run_schedule(
+        &self,
+        system_type: SystemType,
+        game_state: *mut GameState,
+        time: f64,
+        dt: f64,
+    ) {
+        let schedule = self.get_schedule(system_type);
+        let schedule_start = self.profiler.start();
+        for (i, group) in schedule.execution_order.iter().enumerate() {
+            let group_start = self.profiler.start();
+            self.await_group(group, &schedule.systems, game_state, time, dt)
+                .await;
+            self.profiler.record(
+                format_args!("{system_type:?} group {i}"),
+                SpanKind::Group,
+                group_start,
+            );
+        }
+        self.profiler.record(
+            format_args!("{system_type:?}"),
+            SpanKind::Schedule,
+            schedule_start,
+        );
+    }
+
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
     async fn await_group(
-        group: &Vec<usize>,
-        systems: &Vec<System>,
+        &self,
+        group: &[usize],
+        systems: &[System],
         game_state: *mut GameState,
         time: f64,
         dt: f64,
     ) {
+        // skipped systems keep their `last_run`, so they see every change once they run again
+        let group: Vec<usize> = group
+            .iter()
+            .copied()
+            .filter(|index| systems[*index].should_run(unsafe { &*game_state }))
+            .collect();
+        if group.is_empty() {
+            return;
+        }
+
         let mut futures = Vec::with_capacity(group.len());
+        // every system in the group stamps its changes with the same tick
+        let this_run = unsafe { &*game_state }.change_tick();
+        unsafe { &*game_state }
+            .borrow_tracker
+            .begin_group(group.iter().map(|index| &systems[*index]));
 
-        // Run all systems in the group
         for system_index in group.iter() {
             let system = &systems[*system_index];
-            futures.push((system.system)(game_state, time, dt));
+            let context = system.get_context(this_run);
+            let future = context.scope((system.system)(game_state, time, dt));
+            let (id, name, profiler) = (system.id, system.name, self.profiler.clone());
+            futures.push(async move {
+                let start = profiler.start();
+                future.await;
+                profiler.record_system(id, name, start);
+            });
         }
 
-        // Wait for all futures to complete
-        poll_fn(|cx| {
-            for future in futures.iter_mut() {
-                if let std::task::Poll::Pending = future.as_mut().poll(cx) {
-                    return std::task::Poll::Pending;
-                }
+        if self.is_manual_clock() {
+            for future in futures {
+                future.await;
             }
-            std::task::Poll::Ready(())
-        })
-        .await;
-    }
-
-    pub fn generate_execution_order(&mut self) {
-        self.init_execution_order = self.generate_execution_order_for_systems(&self.init_systems);
-        self.update_execution_order =
-            self.generate_execution_order_for_systems(&self.update_systems);
-        self.fixed_update_execution_order =
-            self.generate_execution_order_for_systems(&self.fixed_update_systems);
-        self.close_execution_order = self.generate_execution_order_for_systems(&self.close_systems);
-    }
-
-    fn generate_execution_order_for_systems(&self, systems: &Vec<System>) -> Vec<Vec<usize>> {
-        let mut execution_order = Vec::new();
-        let mut visited = vec![false; systems.len()];
-
-        for i in 0..systems.len() {
-            if !visited[i] {
-                let mut group = vec![i];
-                let mut dissallowed_components = systems[i].args.clone();
-
-                if !dissallowed_components.contains(&GameState::get_component_type()) {
-                    for j in 0..systems.len() {
-                        if !visited[j] {
-                            let mut can_run = true;
-
-                            for component in &systems[j].args {
-                                if dissallowed_components.contains(component)
-                                    || *component == GameState::get_component_type()
-                                {
-                                    can_run = false;
-                                    break;
-                                }
-                            }
-                            if can_run {
-                                group.push(j);
-                                visited[j] = true;
-                                for component in &systems[j].args {
-                                    if !dissallowed_components.contains(component) {
-                                        dissallowed_components.push(*component);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+        } else {
+            Self::run_in_parallel(futures).await;
+        }
 
-                execution_order.push(group);
+        for system_index in group.iter() {
+            systems[*system_index]
+                .last_run
+                .store(this_run, Ordering::Relaxed);
+        }
+        let game_state = unsafe { &mut *game_state };
+        game_state.borrow_tracker.end_group();
+        // so anything written after the group, inside or outside of systems, is newer than it.
+        // That includes the commands the group queued, which its own systems would miss otherwise
+        game_state.increment_change_tick();
+        game_state.apply_commands();
+    }
+
+    // Every future gets its own task, so they are spread over the worker threads of the runtime
+    // this is called from. The grouping guarantees systems in the same group don't access the
+    // same data mutably, `Resource` and `Component` being `Send + Sync` makes sharing the rest
+    // sound, and their context is set inside the task so it follows them around
+    async fn run_in_parallel(futures: Vec<impl Future<Output = ()> + Send + 'static>) {
+        let handles: Vec<_> = futures.into_iter().map(tokio::spawn).collect();
+
+        // Wait for all systems to finish before passing on a panic, none of them may still be
+        // using the game state once this returns
+        let mut panic = None;
+        for handle in handles {
+            if let Err(e) = handle.await {
+                if let (Ok(payload), None) = (e.try_into_panic(), &panic) {
+                    panic = Some(payload);
+                }
             }
         }
+        if let Some(payload) = panic {
+            std::panic::resume_unwind(payload);
+        }
+    }
 
-        execution_order
+    pub fn generate_execution_order(&mut self) -> Result<(), ScheduleError> {
+        self.init.generate_execution_order()?;
+        self.update.generate_execution_order()?;
+        self.fixed_update.generate_execution_order()?;
+        self.close.generate_execution_order()?;
+        for schedule in self.state_schedules.values_mut() {
+            schedule.generate_execution_order()?;
+        }
+        Ok(())
     }
 
+    // seconds since the scheduler was created, or the sum of all steps with a manual clock
     pub fn get_time(&self) -> f64 {
-        self.start_time.elapsed().as_secs_f64()
+        match &self.clock {
+            Clock::Real(start_time) => start_time.elapsed().as_secs_f64(),
+            Clock::Manual(manual_time) => unsafe { *manual_time.get() },
+        }
+    }
+
+    pub fn is_manual_clock(&self) -> bool {
+        matches!(self.clock, Clock::Manual(_))
+    }
+
+    /// How many updates have finished, so the first update sees 0.
+    ///
+    /// Systems can reach it through `GameState::get_scheduler`.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn frame_counter(&self) -> Arc<AtomicU64> {
+        self.frame_count.clone()
+    }
+
+    // what systems get as `t` and `dt`, the virtual clock as of the last update when there is
+    // a `Time` resource
+    fn virtual_time(game_state: &GameState, time: f64, dt: f64) -> (f64, f64) {
+        match game_state.get_resource::<Time>() {
+            Ok(time_resource) => (time_resource.elapsed(), time_resource.delta()),
+            Err(_) => (time, dt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    create_system!(spin, get_spin; writes Transform);
+    async fn spin(game_state: &mut GameState, _t: f64, dt: f64) {
+        for mut transform in game_state.query_mut::<&mut Transform, ()>() {
+            transform.rotation += dt as f32;
+            transform.position[0] += transform.rotation.sin() * dt as f32;
+        }
+    }
+
+    create_system!(spawn_spinners, get_spawn_spinners);
+    async fn spawn_spinners(game_state: &mut GameState, _t: f64, _dt: f64) {
+        let frame = game_state.get_scheduler().frame_count();
+        if !frame.is_multiple_of(3) {
+            return;
+        }
+        game_state
+            .commands()
+            .spawn(format!("spinner {frame}"), move |entity, game_state| {
+                let transform = Transform {
+                    position: [frame as f32, 0.0],
+                    rotation: 0.0,
+                    scale: [1.0, 1.0],
+                };
+                entity.add_component(game_state, transform);
+            });
+    }
+
+    async fn simulate(dts: &[f64]) -> (String, u64) {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0 / 60.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        game_state.register_saveable::<Transform>();
+        game_state.register_saveable::<GlobalTransform>();
+
+        scheduler
+            .add_system(get_spin(), SystemType::FixedUpdate)
+            .unwrap();
+        scheduler
+            .add_system(get_spawn_spinners(), SystemType::Update)
+            .unwrap();
+        scheduler
+            .add_system(get_propagate_transforms_system(), SystemType::Update)
+            .unwrap();
+
+        scheduler.init(&mut game_state).await;
+        for dt in dts {
+            scheduler.step(&mut game_state, *dt).await;
+        }
+        (game_state.save_world().unwrap(), scheduler.frame_count())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stepping_is_deterministic() {
+        let dts: Vec<f64> = (0..120)
+            .map(|i| [1.0 / 60.0, 1.0 / 30.0, 0.007][i % 3])
+            .collect();
+
+        let first = simulate(&dts).await;
+        let second = simulate(&dts).await;
+        assert_eq!(first.1, 120);
+        assert!(first.0.contains("spinner 117"));
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn failed_replacements_are_reported() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(1.0 / 60.0));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        let spawner = scheduler
+            .add_system(get_spawn_spinners().before("spin"), SystemType::Update)
+            .unwrap();
+        let spinner = scheduler
+            .add_system(get_spin(), SystemType::Update)
+            .unwrap();
+        assert_eq!((spawner, spinner), (SystemId(0), SystemId(1)));
+
+        scheduler.replace_system(spinner, get_spin().before("spawn_spinners"));
+        scheduler.step(&mut game_state, 0.1).await;
+        let errors = scheduler.take_system_change_errors();
+        assert!(matches!(errors[..], [SystemChangeError::Cycle(id, _)] if id == spinner));
+        assert!(scheduler.get_system(spinner).unwrap().before.is_empty());
+
+        scheduler.remove_system(spinner);
+        scheduler.remove_system(spinner);
+        scheduler.step(&mut game_state, 0.1).await;
+        let errors = scheduler.take_system_change_errors();
+        assert!(matches!(errors[..], [SystemChangeError::NotFound(id)] if id == spinner));
+        assert!(scheduler.take_system_change_errors().is_empty());
+    }
+
+    static FIXED_TIMES: std::sync::Mutex<Vec<f64>> = std::sync::Mutex::new(Vec::new());
+
+    create_system!(record_fixed_time, get_record_fixed_time);
+    async fn record_fixed_time(_game_state: &mut GameState, t: f64, _dt: f64) {
+        FIXED_TIMES.lock().unwrap().push(t);
+    }
+
+    #[tokio::test]
+    async fn fixed_updates_advance_time_by_the_interval() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(0.25));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        scheduler
+            .add_system(get_record_fixed_time(), SystemType::FixedUpdate)
+            .unwrap();
+
+        scheduler.step(&mut game_state, 0.8).await;
+        scheduler.step(&mut game_state, 0.2).await;
+        assert_eq!(*FIXED_TIMES.lock().unwrap(), [0.25, 0.5, 0.75, 1.0]);
     }
 }
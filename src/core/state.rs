@@ -0,0 +1,134 @@
+use crate::core::*;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// One value of a state machine, like `AppState::Paused`. Declared with `create_states!`.
+pub trait States: Copy + Eq + Hash + Debug + Send + Sync + 'static {
+    // the position of the variant, which `OnEnter` and `OnExit` schedules are looked up by
+    fn index(self) -> u32;
+}
+
+// Declares a state machine for `GameState::add_state`, e.g. `create_states!(AppState { Playing, Paused })`
+macro_rules! create_states {
+    ($(#[$meta:meta])* $name:ident { $($state:ident),+ $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($state),+
+        }
+
+        impl crate::core::States for $name {
+            fn index(self) -> u32 {
+                self as u32
+            }
+        }
+    };
+}
+pub(crate) use create_states;
+
+// A state of any state machine, what `SystemType::OnEnter` and `SystemType::OnExit` take
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StateId {
+    state_type: ComponentType,
+    index: u32,
+}
+
+impl<S: States> From<S> for StateId {
+    fn from(state: S) -> Self {
+        StateId {
+            state_type: get_component_type_of::<S>(),
+            index: state.index(),
+        }
+    }
+}
+
+/// The current state of a state machine added with `GameState::add_state`.
+///
+/// Only changes between frames, when a transition requested through `NextState` is applied.
+pub struct State<S: States>(S);
+
+impl<S: States> State<S> {
+    pub fn get(&self) -> S {
+        self.0
+    }
+}
+
+impl<S: States> Resource for State<S> {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+pub struct NextState<S: States>(Option<S>);
+
+impl<S: States> NextState<S> {
+    // replaces any transition requested earlier in the same frame
+    pub fn set(&mut self, state: S) {
+        self.0 = Some(state);
+    }
+}
+
+impl<S: States> Resource for NextState<S> {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+pub(crate) struct StateTransition {
+    pub exited: Option<StateId>,
+    pub entered: StateId,
+}
+
+fn apply_state_transition<S: States>(game_state: &mut GameState) -> Option<StateTransition> {
+    let next = game_state
+        .get_resource_mut::<NextState<S>>()
+        .ok()?
+        .0
+        .take()?;
+    let current = game_state.state::<S>();
+    if current == Some(next) {
+        return None;
+    }
+
+    game_state.insert_resource(State(next));
+    Some(StateTransition {
+        exited: current.map(StateId::from),
+        entered: next.into(),
+    })
+}
+
+impl GameState {
+    // `State<S>` is inserted by the first transition, applied right after the Init schedule,
+    // which also runs the `OnEnter` schedule of `initial`
+    pub fn add_state<S: States>(&mut self, initial: S) {
+        if self.contains_resource::<NextState<S>>() {
+            return;
+        }
+        self.insert_resource(NextState(Some(initial)));
+        self.state_transitions.push(apply_state_transition::<S>);
+    }
+
+    pub fn state<S: States>(&self) -> Option<S> {
+        self.get_resource::<State<S>>().ok().map(State::get)
+    }
+
+    pub fn set_next_state<S: States>(&mut self, state: S) -> Result<(), ResourceError> {
+        self.get_resource_mut::<NextState<S>>()?.set(state);
+        Ok(())
+    }
+
+    // applied by the `Scheduler`, which runs the `OnExit` and `OnEnter` schedules of every
+    // returned transition
+    pub(crate) fn apply_state_transitions(&mut self) -> Vec<StateTransition> {
+        self.state_transitions
+            .clone()
+            .into_iter()
+            .filter_map(|apply| apply(self))
+            .collect()
+    }
+}
+
+// run condition for `System::run_if`, e.g. `get_rotate_system().run_if(in_state(AppState::Playing))`
+pub fn in_state<S: States>(state: S) -> impl Fn(&GameState) -> bool + Send + Sync + 'static {
+    move |game_state| game_state.state::<S>() == Some(state)
+}
@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+
+/// Handle to an entity in a `GameState`.
+///
+/// `index` is the entity's slot and `generation` is bumped every time that slot is freed, so a
+/// handle to a despawned entity never resolves to whatever reuses the slot afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EntityId {
+    pub index: u32,
+    pub generation: u32,
+}
+
+impl std::fmt::Display for EntityId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+/// The change ticks a component was added and last mutably accessed at.
+///
+/// Ticks wrap around, so they are only ever compared relative to the tick a system is running
+/// at, never against each other directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: u32) -> Self {
+        ComponentTicks {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    pub fn is_added(&self, last_run: u32, this_run: u32) -> bool {
+        is_tick_newer(self.added, last_run, this_run)
+    }
+
+    pub fn is_changed(&self, last_run: u32, this_run: u32) -> bool {
+        is_tick_newer(self.changed, last_run, this_run)
+    }
+}
+
+// whether `tick` happened after `last_run`, as seen from `this_run`
+pub fn is_tick_newer(tick: u32, last_run: u32, this_run: u32) -> bool {
+    this_run.wrapping_sub(last_run) > this_run.wrapping_sub(tick)
+}
+
+const EMPTY: u32 = u32::MAX;
+
+/// Stores every `T` contiguously and unboxed.
+///
+/// `sparse` maps an entity's index to its position in the dense `entities` and `data` vectors,
+/// so lookups are O(1) and iteration walks plain slices. Removal swaps the last element into
+/// the hole, which means the order of `data` is not stable. `ticks` runs parallel to `data`.
+pub struct SparseSet<T> {
+    sparse: Vec<u32>,
+    entities: Vec<EntityId>,
+    data: Vec<T>,
+    ticks: Vec<ComponentTicks>,
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SparseSet<T> {
+    pub fn new() -> Self {
+        SparseSet {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            data: Vec::new(),
+            ticks: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn dense_index(&self, entity: EntityId) -> Option<usize> {
+        let dense = *self.sparse.get(entity.index as usize)?;
+        if dense == EMPTY || self.entities[dense as usize] != entity {
+            return None;
+        }
+        Some(dense as usize)
+    }
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.dense_index(entity).is_some()
+    }
+
+    pub fn get(&self, entity: EntityId) -> Option<&T> {
+        self.dense_index(entity).map(|dense| &self.data[dense])
+    }
+
+    pub fn get_mut(&mut self, entity: EntityId) -> Option<&mut T> {
+        self.dense_index(entity).map(|dense| &mut self.data[dense])
+    }
+
+    pub fn get_ticks(&self, entity: EntityId) -> Option<&ComponentTicks> {
+        self.dense_index(entity).map(|dense| &self.ticks[dense])
+    }
+
+    pub fn get_with_ticks_mut(
+        &mut self,
+        entity: EntityId,
+    ) -> Option<(&mut T, &mut ComponentTicks)> {
+        self.dense_index(entity)
+            .map(|dense| (&mut self.data[dense], &mut self.ticks[dense]))
+    }
+
+    // returns the previous value if the entity already had one, which counts as a change rather
+    // than an addition
+    pub fn insert(&mut self, entity: EntityId, value: T, tick: u32) -> Option<T> {
+        let index = entity.index as usize;
+        if index >= self.sparse.len() {
+            self.sparse.resize(index + 1, EMPTY);
+        }
+
+        let dense = self.sparse[index];
+        if dense != EMPTY {
+            let dense = dense as usize;
+            // a stale generation in the slot is overwritten like any other value
+            self.entities[dense] = entity;
+            self.ticks[dense].changed = tick;
+            return Some(std::mem::replace(&mut self.data[dense], value));
+        }
+
+        self.sparse[index] = self.data.len() as u32;
+        self.entities.push(entity);
+        self.data.push(value);
+        self.ticks.push(ComponentTicks::new(tick));
+        None
+    }
+
+    pub fn remove(&mut self, entity: EntityId) -> Option<T> {
+        let dense = self.dense_index(entity)?;
+
+        self.sparse[entity.index as usize] = EMPTY;
+        self.entities.swap_remove(dense);
+        self.ticks.swap_remove(dense);
+        let value = self.data.swap_remove(dense);
+
+        if let Some(moved) = self.entities.get(dense) {
+            self.sparse[moved.index as usize] = dense as u32;
+        }
+
+        Some(value)
+    }
+
+    pub fn entities(&self) -> &[EntityId] {
+        &self.entities
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    pub fn ticks_mut(&mut self) -> &mut [ComponentTicks] {
+        &mut self.ticks
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.entities.iter().copied().zip(self.data.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+        self.entities.iter().copied().zip(self.data.iter_mut())
+    }
+}
@@ -0,0 +1,175 @@
+use crate::core::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Timing of the current update, kept up to date by the `Scheduler` before each update.
+///
+/// There are two clocks: the real one, which always follows the time passed to the scheduler,
+/// and the virtual one, which is scaled by `time_scale` and stands still while paused. Fixed
+/// updates are driven by the virtual clock, so pausing or slowing it down pauses or slows down
+/// the simulation while the UI and rendering keep running at full speed. The `t` and `dt` systems
+/// get are the virtual clock as well.
+pub struct Time {
+    real_delta: f64,
+    real_elapsed: f64,
+    delta: f64,
+    elapsed: f64,
+    time_scale: f64,
+    paused: bool,
+    // the `Scheduler`'s own counter
+    frame_count: Arc<AtomicU64>,
+}
+impl_resource!(Time);
+
+impl Time {
+    pub fn new(scheduler: &Scheduler) -> Self {
+        Time {
+            real_delta: 0.0,
+            real_elapsed: 0.0,
+            delta: 0.0,
+            elapsed: 0.0,
+            time_scale: 1.0,
+            paused: false,
+            frame_count: scheduler.frame_counter(),
+        }
+    }
+
+    // seconds on the virtual clock since the last update
+    pub fn delta(&self) -> f64 {
+        self.delta
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    pub fn real_delta(&self) -> f64 {
+        self.real_delta
+    }
+
+    pub fn real_elapsed(&self) -> f64 {
+        self.real_elapsed
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    // e.g. 0.2 for bullet time, takes effect from the next update on
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        assert!(
+            time_scale.is_finite() && time_scale >= 0.0,
+            "time scale has to be finite and not negative, got {time_scale}"
+        );
+        self.time_scale = time_scale;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn unpause(&mut self) {
+        self.paused = false;
+    }
+
+    // updates finished before this one, same as `Scheduler::frame_count`
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count.load(Ordering::Relaxed)
+    }
+
+    // returns the virtual delta
+    pub(crate) fn advance(&mut self, real_elapsed: f64, real_delta: f64) -> f64 {
+        self.real_elapsed = real_elapsed;
+        self.real_delta = real_delta;
+        self.delta = if self.paused {
+            0.0
+        } else {
+            real_delta * self.time_scale
+        };
+        self.elapsed += self.delta;
+        self.delta
+    }
+}
+
+/// Timing of fixed updates, which always advance the virtual clock by `timestep`.
+pub struct FixedTime {
+    timestep: f64,
+    elapsed: f64,
+    step_count: u64,
+    alpha: f64,
+}
+impl_resource!(FixedTime);
+
+impl FixedTime {
+    pub fn new(timestep: f64) -> Self {
+        FixedTime {
+            timestep,
+            elapsed: 0.0,
+            step_count: 0,
+            alpha: 0.0,
+        }
+    }
+
+    pub fn timestep(&self) -> f64 {
+        self.timestep
+    }
+
+    // virtual time simulated by fixed updates, including the one running
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    // fixed updates run so far, including the one running
+    pub fn step_count(&self) -> u64 {
+        self.step_count
+    }
+
+    // same as `Scheduler::fixed_update_alpha`, as of the last update
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    pub(crate) fn advance(&mut self) {
+        self.elapsed += self.timestep;
+        self.step_count += 1;
+    }
+
+    pub(crate) fn set_alpha(&mut self, alpha: f64) {
+        self.alpha = alpha;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[tokio::test]
+    async fn pause_and_time_scale_change_how_many_fixed_updates_run() {
+        let mut scheduler = Box::pin(Scheduler::with_manual_clock(0.25));
+        let mut game_state = Box::pin(GameState::with_config(&mut *scheduler, Config::default()));
+        let step_count =
+            |game_state: &GameState| game_state.get_resource::<FixedTime>().unwrap().step_count();
+
+        scheduler.step(&mut game_state, 1.0).await;
+        assert_eq!(step_count(&game_state), 4);
+
+        game_state
+            .get_resource_mut::<Time>()
+            .unwrap()
+            .set_time_scale(0.5);
+        scheduler.step(&mut game_state, 1.0).await;
+        assert_eq!(step_count(&game_state), 6);
+
+        game_state.get_resource_mut::<Time>().unwrap().pause();
+        scheduler.step(&mut game_state, 1.0).await;
+        assert_eq!(step_count(&game_state), 6);
+
+        let time = game_state.get_resource::<Time>().unwrap();
+        assert_eq!((time.elapsed(), time.real_elapsed()), (1.5, 3.0));
+        assert_eq!(time.frame_count(), 3);
+    }
+}
@@ -0,0 +1,88 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+
+use crate::core::ComponentType;
+
+// Components, resources and anything else a system can declare access to share one id space,
+// so two types can never end up with the same `ComponentType`
+#[derive(Default)]
+struct TypeRegistry {
+    ids: HashMap<TypeId, ComponentType>,
+    names: Vec<&'static str>,
+}
+
+lazy_static! {
+    static ref TYPE_REGISTRY: RwLock<TypeRegistry> = RwLock::new(TypeRegistry::default());
+}
+
+// assigns the next free id the first time a type is seen. Types that ask for their id every
+// frame should go through `cached_component_type!` instead of taking the lock each time
+pub fn get_component_type_of<T: 'static>() -> ComponentType {
+    let type_id = TypeId::of::<T>();
+
+    if let Some(component_type) = TYPE_REGISTRY.read().unwrap().ids.get(&type_id) {
+        return *component_type;
+    }
+
+    let mut registry = TYPE_REGISTRY.write().unwrap();
+    // another thread may have registered the type between dropping the read lock and locking
+    if let Some(component_type) = registry.ids.get(&type_id) {
+        return *component_type;
+    }
+
+    let component_type = registry.names.len();
+    registry.ids.insert(type_id, component_type);
+    registry.names.push(std::any::type_name::<T>());
+
+    component_type
+}
+
+// The id of `Self`, looked up in the registry once and then read from a per-type static. Only
+// usable in non-generic impls, since a static can't depend on the impl's type parameters
+macro_rules! cached_component_type {
+    () => {{
+        static COMPONENT_TYPE: std::sync::OnceLock<crate::core::ComponentType> =
+            std::sync::OnceLock::new();
+        *COMPONENT_TYPE.get_or_init(crate::core::get_component_type_of::<Self>)
+    }};
+}
+pub(crate) use cached_component_type;
+
+pub fn get_component_type_name(component_type: ComponentType) -> Option<&'static str> {
+    TYPE_REGISTRY
+        .read()
+        .unwrap()
+        .names
+        .get(component_type)
+        .copied()
+}
+
+// `a::b::Foo<c::Bar>` becomes `Foo<c::Bar>`
+pub fn short_type_name(type_name: &str) -> String {
+    let (path, generics) = type_name.split_at(type_name.find('<').unwrap_or(type_name.len()));
+    let short = path.rsplit("::").next().unwrap_or(path);
+    format!("{short}{generics}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn cached_ids_match_the_registry() {
+        let transform = Transform::get_component_type();
+        assert_eq!(transform, Transform::get_component_type());
+        assert_eq!(transform, get_component_type_of::<Transform>());
+        assert_eq!(
+            <Time as Resource>::get_component_type(),
+            get_component_type_of::<Time>()
+        );
+        assert_eq!(
+            GameState::get_component_type(),
+            get_component_type_of::<GameState>()
+        );
+    }
+}
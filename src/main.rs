@@ -1,7 +1,6 @@
 #![feature(sync_unsafe_cell)]
-#![feature(trait_upcasting)]
-#![feature(downcast_unchecked)]
 #![feature(variant_count)]
+#![cfg_attr(test, feature(test))]
 
 use std::sync::Arc;
 
@@ -42,10 +41,20 @@ pub struct Config {
     pub inner_size: [f32; 2],
 }
 
+// what `config.toml` ships with, for running without the resources directory
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            worker_threads: 8,
+            inner_size: [1280.0, 720.0],
+        }
+    }
+}
+
 fn main() -> ! {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size(&CONFIG.inner_size)
+            .with_inner_size(CONFIG.inner_size)
             .with_resizable(true)
             .with_title("Klaus Of Death"),
         renderer: eframe::Renderer::Wgpu,
@@ -66,6 +75,7 @@ fn main() -> ! {
         Box::new(|cc| {
             let render_state = cc.wgpu_render_state.clone().unwrap();
             let app = RT.block_on(App::new(render_state));
+            RT.spawn(close_on_ctrl_c(cc.egui_ctx.clone()));
 
             Ok(Box::new(app))
         }),
@@ -75,26 +85,16 @@ fn main() -> ! {
     std::process::exit(0);
 }
 
-struct SendBox<T>(std::pin::Pin<Box<T>>);
-
-unsafe impl<T> Send for SendBox<T> {}
-
-impl<T> SendBox<T> {
-    unsafe fn _new(t: T) -> Self {
-        SendBox(Box::pin(t))
+// The first Ctrl+C closes the window, so the Close schedule runs like for any other way of
+// closing. A second one exits right away in case that hangs
+async fn close_on_ctrl_c(ctx: egui::Context) {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        return eprintln!("Failed to listen for Ctrl+C: {e}");
     }
-}
-
-impl<T> futures::Future for SendBox<T>
-where
-    T: futures::Future,
-{
-    type Output = T::Output;
+    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+    ctx.request_repaint();
 
-    fn poll(
-        mut self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context,
-    ) -> std::task::Poll<Self::Output> {
-        self.0.as_mut().poll(cx)
+    if tokio::signal::ctrl_c().await.is_ok() {
+        std::process::exit(130);
     }
 }
@@ -25,7 +25,7 @@ pub struct Networking {
 
     pub connected: bool,
 }
-impl_resource!(Networking, 0);
+impl_resource!(Networking);
 
 impl Networking {
     pub fn new(info: NetworkingCreationInfo) -> Self {
@@ -9,7 +9,7 @@ pub struct GpuDevice {
     pub shaders: HashMap<String, ShaderModule>,
     pub output_tex: wgpu::Texture,
 }
-impl_resource!(GpuDevice, 1);
+impl_resource!(GpuDevice);
 
 #[inline]
 pub fn pad_to_multiple_of_256(n: u32) -> u32 {
@@ -71,7 +71,7 @@ impl GpuDevice {
             let relative_file = relative_file.replace("\\", "/");
 
             #[cfg(debug_assertions)]
-            print!("Loaded shader: {}\n", relative_file);
+            println!("Loaded shader: {}", relative_file);
             shaders.insert(relative_file, shader);
         }
 
@@ -6,7 +6,7 @@ pub struct Map {
     pub buffer: Buffer,
     pub bitmap: Box<[u8; 64 * 64 / 8]>,
 }
-impl_resource!(Map, 2);
+impl_resource!(Map);
 
 impl Map {
     pub fn load(gpu: &GpuDevice, resource: &str) -> Self {
@@ -57,17 +57,17 @@ impl Map {
                 if bit == 1 {
                     let i = i as f32 + 0.5;
                     let j = j as f32 + 0.5;
-                    let x_dif = (x - i as f32).abs();
-                    let y_dif = (y - j as f32).abs();
+                    let x_dif = (x - i).abs();
+                    let y_dif = (y - j).abs();
 
                     if x_dif > y_dif {
-                        if x > i as f32 {
+                        if x > i {
                             dirs.push(Direction::Right);
                         } else {
                             dirs.push(Direction::Left);
                         }
                     } else {
-                        if y > j as f32 {
+                        if y > j {
                             dirs.push(Direction::Down);
                         } else {
                             dirs.push(Direction::Up);
@@ -14,11 +14,15 @@ pub struct RenderState {
     render_pipeline: ComputePipeline,
     render_bind_group: BindGroup,
 }
-impl_resource!(RenderState, 3);
+impl_resource!(RenderState);
 
-create_system!(init, get_init_system);
+create_system!(init, get_init_system;
+    writes GameState);
 async fn init(game_state: &mut GameState, _time: f64, _dt: f64) {
-    let gpu = game_state.get_resource::<GpuDevice>().unwrap();
+    let gpu = match game_state.get_resource::<GpuDevice>() {
+        Ok(gpu) => gpu,
+        Err(e) => return e.report(),
+    };
     let map = Map::load(gpu, "map/default.map");
 
     let ray_pipeline_desc = ComputePipelineDescriptor {
@@ -120,40 +124,32 @@ async fn init(game_state: &mut GameState, _time: f64, _dt: f64) {
             ],
         });
 
-    game_state.add_resource(map);
-    game_state.add_resource(RenderState {
+    game_state.insert_resource(map);
+    game_state.insert_resource(RenderState {
         ray_pipeline,
         player_buffer,
         ray_bind_group,
         render_pipeline,
         render_bind_group,
     });
-
-    let entity = game_state.create_entity("Player".to_string());
-    entity.add_component(game_state, Player, Player::get_component_type());
-    entity.add_component(
-        game_state,
-        Transform {
-            position: [15.0, 20.0],
-            rotation: 0.0,
-            scale: [1.0, 1.0],
-        },
-        Transform::get_component_type(),
-    )
 }
 
 use crate::utils::*;
 use map::Map;
 
 create_system!(render, get_render_system;
-    uses RenderState, Map, Player, Transform);
+    reads GpuDevice, RenderState, Map, Player, Transform);
 pub async fn render(game_state: &mut GameState, _t: f64, _dt: f64) {
-    let gpu = game_state.get_resource::<GpuDevice>().unwrap();
+    let (gpu, render_state) = match (
+        game_state.get_resource::<GpuDevice>(),
+        game_state.get_resource::<RenderState>(),
+    ) {
+        (Ok(gpu), Ok(render_state)) => (gpu, render_state),
+        (Err(e), _) | (_, Err(e)) => return e.report(),
+    };
     let player = game_state
-        .get_entities_with::<Player>(Player::get_component_type())
-        .first()
-        .unwrap()
-        .get_component::<Transform>(Transform::get_component_type())
+        .query::<&Transform, With<Player>>()
+        .single()
         .unwrap();
     let player = [
         player.position[0],
@@ -167,7 +163,7 @@ pub async fn render(game_state: &mut GameState, _t: f64, _dt: f64) {
         ray_bind_group,
         render_pipeline,
         render_bind_group,
-    } = game_state.get_resource::<RenderState>().unwrap();
+    } = render_state;
 
     gpu.render_state
         .queue
@@ -184,7 +180,7 @@ pub async fn render(game_state: &mut GameState, _t: f64, _dt: f64) {
             timestamp_writes: None,
         });
         compute_pass.set_pipeline(ray_pipeline);
-        compute_pass.set_bind_group(0, &ray_bind_group, &[]);
+        compute_pass.set_bind_group(0, ray_bind_group, &[]);
         compute_pass.dispatch_workgroups(1920 / 8, 1, 1);
     }
 
@@ -194,7 +190,7 @@ pub async fn render(game_state: &mut GameState, _t: f64, _dt: f64) {
             timestamp_writes: None,
         });
         compute_pass.set_pipeline(render_pipeline);
-        compute_pass.set_bind_group(0, &render_bind_group, &[]);
+        compute_pass.set_bind_group(0, render_bind_group, &[]);
         compute_pass.dispatch_workgroups(1920 / 16, 1080 / 16, 1);
     }
 
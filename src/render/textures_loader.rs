@@ -35,15 +35,11 @@ impl TextureLoader {
 
         let textures = textures
             .iter()
-            .map(|texture| {
-                let texture = texture.as_raw();
-                texture
-            })
-            .flatten()
+            .flat_map(|texture| texture.as_raw())
             .cloned()
             .collect::<Vec<u8>>();
 
-        let texture = gpu.render_state.device.create_texture_with_data(
+        gpu.render_state.device.create_texture_with_data(
             &gpu.render_state.queue,
             &TextureDescriptor {
                 label: None,
@@ -61,8 +57,6 @@ impl TextureLoader {
             },
             util::TextureDataOrder::LayerMajor,
             &textures,
-        );
-
-        texture
+        )
     }
 }
@@ -2,12 +2,16 @@ use crate::*;
 use egui::Event;
 use egui::Key;
 use std::sync::mpsc;
+use std::sync::Mutex;
 
 pub struct Input {
-    rx: mpsc::Receiver<Vec<Event>>,
+    // only used through `&mut self`, the mutex just makes `Input` shareable between systems
+    rx: Mutex<mpsc::Receiver<Vec<Event>>>,
     keys_down: Vec<bool>,
+    // keys that went down since the previous `handle_events`
+    keys_pressed: Vec<bool>,
 }
-impl_resource!(Input, 3);
+impl_resource!(Input);
 
 pub struct InputSender {
     pub tx: mpsc::Sender<Vec<Event>>,
@@ -17,20 +21,33 @@ impl Input {
     pub fn new() -> (Self, InputSender) {
         let key_count = std::mem::variant_count::<Key>();
         let keys_down = vec![false; key_count];
+        let keys_pressed = vec![false; key_count];
         let (tx, rx) = mpsc::channel();
 
-        (Self { rx, keys_down }, InputSender { tx })
+        (
+            Self {
+                rx: Mutex::new(rx),
+                keys_down,
+                keys_pressed,
+            },
+            InputSender { tx },
+        )
     }
 
     pub fn handle_events(&mut self) {
-        let events = self.rx.recv().unwrap();
+        let events = self.rx.get_mut().unwrap().recv().unwrap();
+        self.keys_pressed.fill(false);
         for event in events {
-            match event {
-                Event::Key { key, pressed, .. } => {
-                    let key_idx = key as usize;
-                    self.keys_down[key_idx] = pressed;
-                }
-                _ => (),
+            if let Event::Key {
+                key,
+                pressed,
+                repeat,
+                ..
+            } = event
+            {
+                let key_idx = key as usize;
+                self.keys_down[key_idx] = pressed;
+                self.keys_pressed[key_idx] |= pressed && !repeat;
             }
         }
     }
@@ -38,11 +55,18 @@ impl Input {
     pub fn is_key_pressed(&self, key: Key) -> bool {
         self.keys_down[key as usize]
     }
+
+    // only true for the frame the key went down in, for toggles
+    pub fn is_key_just_pressed(&self, key: Key) -> bool {
+        self.keys_pressed[key as usize]
+    }
 }
 
 create_system!(handle_input, get_handle_input_system;
-    uses Input);
+    writes Input);
 pub async fn handle_input(game_state: &mut GameState, _t: f64, _dt: f64) {
-    let input = game_state.get_resource_mut::<Input>().unwrap();
-    input.handle_events();
+    match game_state.get_resource_mut::<Input>() {
+        Ok(input) => input.handle_events(),
+        Err(e) => e.report(),
+    }
 }
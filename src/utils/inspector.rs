@@ -0,0 +1,175 @@
+use crate::*;
+use egui::*;
+use std::collections::HashMap;
+
+// in the working directory, open it with `chrome://tracing` or Perfetto
+const TRACE_PATH: &str = "trace.json";
+
+// returns whether the component was edited
+type Editor = Box<dyn Fn(&mut Ui, &mut dyn Component) -> bool>;
+
+/// Side panel showing every entity with its components, every resource and, while profiling,
+/// how long each system takes. Toggled with F1.
+///
+/// Components are shown through their `Debug` output unless an editor was registered for
+/// their type with `register_editor`, in which case they can be changed in place.
+pub struct Inspector {
+    pub open: bool,
+    editors: HashMap<ComponentType, Editor>,
+}
+
+impl Default for Inspector {
+    fn default() -> Self {
+        let mut inspector = Inspector {
+            open: false,
+            editors: HashMap::new(),
+        };
+        inspector.register_editor::<Transform>(edit_transform);
+        inspector
+    }
+}
+
+fn edit_transform(ui: &mut Ui, transform: &mut Transform) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("position");
+        changed |= ui
+            .add(DragValue::new(&mut transform.position[0]).speed(0.1))
+            .changed();
+        changed |= ui
+            .add(DragValue::new(&mut transform.position[1]).speed(0.1))
+            .changed();
+    });
+    ui.horizontal(|ui| {
+        ui.label("rotation");
+        changed |= ui.drag_angle(&mut transform.rotation).changed();
+    });
+    ui.horizontal(|ui| {
+        ui.label("scale");
+        changed |= ui
+            .add(DragValue::new(&mut transform.scale[0]).speed(0.01))
+            .changed();
+        changed |= ui
+            .add(DragValue::new(&mut transform.scale[1]).speed(0.01))
+            .changed();
+    });
+    changed
+}
+
+impl Inspector {
+    pub fn register_editor<T: Component>(&mut self, editor: fn(&mut Ui, &mut T) -> bool) {
+        self.editors.insert(
+            T::get_component_type(),
+            Box::new(move |ui, component| {
+                editor(ui, component.as_any_mut().downcast_mut::<T>().unwrap())
+            }),
+        );
+    }
+
+    // has to be called before the central panel is added, and never while systems are running
+    pub fn show(&mut self, ctx: &Context, game_state: &mut GameState) {
+        if ctx.input(|input| input.key_pressed(Key::F1)) {
+            self.open = !self.open;
+        }
+        if !self.open {
+            return;
+        }
+
+        SidePanel::right("inspector")
+            .resizable(true)
+            .default_width(300.0)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().show(ui, |ui| {
+                    ui.heading("Entities");
+                    self.show_entities(ui, game_state);
+
+                    ui.separator();
+
+                    ui.heading("Resources");
+                    let mut resources: Vec<String> = game_state
+                        .resources
+                        .values()
+                        .map(|resource| short_type_name(resource.type_name()))
+                        .collect();
+                    resources.sort();
+                    for resource in resources {
+                        ui.label(resource);
+                    }
+
+                    ui.separator();
+
+                    ui.heading("Systems");
+                    show_profiler(ui, game_state.get_scheduler().profiler());
+                });
+            });
+    }
+
+    fn show_entities(&self, ui: &mut Ui, game_state: &GameState) {
+        for slot in &game_state.entities {
+            let Some(entity) = &slot.entity else {
+                continue;
+            };
+            let entity = unsafe { &*entity.get() };
+
+            CollapsingHeader::new(format!("{} ({})", entity.name(), entity.id))
+                .id_salt(entity.id)
+                .show(ui, |ui| {
+                    for component_type in &entity.components {
+                        self.show_component(ui, game_state, entity.id, *component_type);
+                    }
+                });
+        }
+    }
+
+    fn show_component(
+        &self,
+        ui: &mut Ui,
+        game_state: &GameState,
+        entity: EntityId,
+        component_type: ComponentType,
+    ) {
+        let Some(storage) = game_state.get_storage_by_type_mut(component_type) else {
+            return;
+        };
+        let Some(component) = storage.get_dyn_mut(entity) else {
+            return;
+        };
+        let name = get_component_type_name(component_type)
+            .map_or_else(|| format!("<{component_type}>"), short_type_name);
+
+        ui.strong(name);
+        match self.editors.get(&component_type) {
+            Some(editor) => {
+                if editor(ui, component) {
+                    storage.set_changed(entity, game_state.change_tick());
+                }
+            }
+            None => {
+                ui.monospace(format!("{component:#?}"));
+            }
+        }
+    }
+}
+
+fn show_profiler(ui: &mut Ui, profiler: &Profiler) {
+    let mut enabled = profiler.is_enabled();
+    ui.horizontal(|ui| {
+        if ui.checkbox(&mut enabled, "profile").changed() {
+            profiler.set_enabled(enabled);
+        }
+        if ui.button("save trace").clicked() {
+            match profiler.save_chrome_trace(TRACE_PATH) {
+                Ok(()) => eprintln!("Saved trace to {TRACE_PATH}"),
+                Err(e) => eprintln!("Failed to save trace to {TRACE_PATH}: {e}"),
+            }
+        }
+    });
+
+    Grid::new("system_averages").striped(true).show(ui, |ui| {
+        for (_, system, average) in profiler.system_averages() {
+            ui.label(system);
+            ui.monospace(format!("{:.3} ms", average.as_secs_f64() * 1000.0));
+            ui.end_row();
+        }
+    });
+}
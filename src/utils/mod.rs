@@ -6,3 +6,6 @@ pub use player::*;
 
 pub mod input;
 pub use input::*;
+
+pub mod inspector;
+pub use inspector::*;
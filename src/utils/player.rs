@@ -1,5 +1,3 @@
 use crate::*;
 
-#[derive(Debug)]
-pub struct Player;
-impl_component!(Player, 1);
+create_tag!(Player);
@@ -1,9 +1,107 @@
 use crate::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Transform {
     pub position: [f32; 2],
     pub rotation: f32,
     pub scale: [f32; 2],
 }
-impl_component!(Transform, 0);
+impl_component!(Transform);
+
+/// An entity's `Transform` combined with those of all its ancestors.
+///
+/// Written by `propagate_transforms` every frame, so it should be read rather than edited.
+/// Entities only get one if it is added alongside their `Transform`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GlobalTransform {
+    pub position: [f32; 2],
+    pub rotation: f32,
+    pub scale: [f32; 2],
+}
+impl_component!(GlobalTransform);
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl From<&Transform> for GlobalTransform {
+    fn from(transform: &Transform) -> Self {
+        GlobalTransform {
+            position: transform.position,
+            rotation: transform.rotation,
+            scale: transform.scale,
+        }
+    }
+}
+
+impl GlobalTransform {
+    pub const IDENTITY: GlobalTransform = GlobalTransform {
+        position: [0.0, 0.0],
+        rotation: 0.0,
+        scale: [1.0, 1.0],
+    };
+
+    // uses the same convention as the renderer, where a rotation of `r` faces `(sin r, cos r)`
+    pub fn transform_point(&self, point: [f32; 2]) -> [f32; 2] {
+        let x = point[0] * self.scale[0];
+        let y = point[1] * self.scale[1];
+        let sin = f32::sin(self.rotation);
+        let cos = f32::cos(self.rotation);
+
+        [
+            self.position[0] + x * cos + y * sin,
+            self.position[1] - x * sin + y * cos,
+        ]
+    }
+
+    pub fn mul_transform(&self, local: &Transform) -> GlobalTransform {
+        GlobalTransform {
+            position: self.transform_point(local.position),
+            rotation: self.rotation + local.rotation,
+            scale: [
+                self.scale[0] * local.scale[0],
+                self.scale[1] * local.scale[1],
+            ],
+        }
+    }
+}
+
+create_system!(propagate_transforms, get_propagate_transforms_system;
+    reads Transform, Parent, Children; writes GlobalTransform);
+pub async fn propagate_transforms(game_state: &mut GameState, _t: f64, _dt: f64) {
+    let roots: Vec<(EntityId, GlobalTransform)> = game_state
+        .query::<(EntityId, &Transform), Without<Parent>>()
+        .iter()
+        .map(|(entity, transform)| (entity, transform.into()))
+        .collect();
+
+    for (entity, global) in roots {
+        propagate(game_state, entity, global);
+    }
+}
+
+fn propagate(game_state: &mut GameState, entity: EntityId, global: GlobalTransform) {
+    if let Some(mut current) = game_state
+        .query_mut::<&mut GlobalTransform, ()>()
+        .get_mut(entity)
+    {
+        // only write on an actual change so `Changed<GlobalTransform>` stays meaningful
+        if *current != global {
+            *current = global;
+        }
+    }
+
+    let Some(children) = game_state.get_component::<Children>(entity) else {
+        return;
+    };
+
+    for child in &children.0 {
+        // children without a `Transform` cut the chain, along with everything below them
+        if let Some(local) = game_state.get_component::<Transform>(*child) {
+            propagate(game_state, *child, global.mul_transform(local));
+        }
+    }
+}